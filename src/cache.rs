@@ -0,0 +1,212 @@
+use {
+    crate::*,
+    ::serde::{Deserialize, Serialize},
+    ::std::{
+        fs::{self, File},
+        os::unix::fs::MetadataExt,
+        path::Path,
+    },
+};
+
+/// The directories that we keep generated crates, binaries and build output in.
+#[derive(Debug, Clone)]
+pub struct DataDirs {
+    /// `~/.rust-exe`, containing our configuration and the directories below.
+    pub data_dir: PathBuf,
+    /// The generated crates, named by their crate names.
+    pub src_dir: PathBuf,
+    /// The compiled binaries, named by their crate names and profiles.
+    pub bin_dir: PathBuf,
+    /// The cache entries for each binary, and the lock files for each crate.
+    pub index_dir: PathBuf,
+    /// The shared cargo target directory, in the system temporary directory.
+    pub tmp_dir: PathBuf,
+}
+
+impl DataDirs {
+    /// Returns our directories, creating any that don't exist yet.
+    pub fn new() -> Result<Self> {
+        let data_dir = ::home::home_dir().unwrap_or_default().join(".rust-exe");
+        let dirs = DataDirs {
+            src_dir: data_dir.join("src"),
+            bin_dir: data_dir.join("bin"),
+            index_dir: data_dir.join("index"),
+            tmp_dir: std::env::temp_dir().join("rust-exe"),
+            data_dir,
+        };
+
+        for dir in [&dirs.src_dir, &dirs.bin_dir, &dirs.index_dir, &dirs.tmp_dir] {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        Ok(dirs)
+    }
+}
+
+/// What we know about a binary that we've compiled for a script, stored in
+/// `~/.rust-exe/index/{binary_name}.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CacheEntry {
+    /// The `{kebab}-{path8}` name of the generated crate and its binary.
+    pub crate_name: String,
+    /// The cargo profile that the binary was built with.
+    #[serde(default = "dev_profile")]
+    pub profile: String,
+    /// The path of the script, as it was given to us.
+    pub source_path: PathBuf,
+    /// The git hash of the script's source files.
+    pub content_hash: String,
+    /// A hash of everything that went into the cached binary: the source, the
+    /// generated manifest, and the toolchain.
+    pub build_hash: String,
+    /// When the binary was last used, in seconds since the unix epoch.
+    #[serde(default)]
+    pub last_used: u64,
+    /// The profile that the script's own metadata chooses, which the binary was
+    /// built with unless another profile was asked for.
+    #[serde(default = "dev_profile")]
+    pub script_profile: String,
+    /// The paths of the script's module files.
+    #[serde(default)]
+    pub modules: Vec<PathBuf>,
+    /// A hash of the inputs that we can check before generating the crate (see
+    /// [`input_hash`]), or empty for entries written by older versions.
+    #[serde(default)]
+    pub input_hash: String,
+}
+
+fn dev_profile() -> String {
+    "dev".to_string()
+}
+
+/// Returns the name of a crate's binary when built with `profile`: the crate
+/// name for the default `dev` profile, or `{crate_name}-{profile}` otherwise.
+pub fn binary_name(crate_name: &str, profile: &str) -> String {
+    if profile == "dev" {
+        crate_name.to_string()
+    } else {
+        format!("{crate_name}-{profile}")
+    }
+}
+
+/// Returns the directory within the target directory that cargo puts the
+/// output of a profile in. The built-in `test` and `bench` profiles inherit
+/// from `dev` and `release`, and share their directories.
+pub fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "release" | "bench" => "release",
+        profile => profile,
+    }
+}
+
+impl CacheEntry {
+    pub fn path(index_dir: &Path, binary_name: &str) -> PathBuf {
+        index_dir.join(format!("{binary_name}.toml"))
+    }
+
+    /// Loads an index entry, returning `None` if it's missing or unreadable.
+    pub fn load(index_dir: &Path, binary_name: &str) -> Option<Self> {
+        let path = Self::path(index_dir, binary_name);
+        let toml = std::fs::read_to_string(&path).ok()?;
+        toml_edit::easy::from_str(&toml)
+            .map_err(|err| debug!("ignoring invalid cache entry {path:?}: {err}"))
+            .ok()
+    }
+
+    /// Loads the index entries for all of a crate's binaries.
+    pub fn load_crate(index_dir: &Path, crate_name: &str) -> Vec<Self> {
+        let prefix = format!("{crate_name}-");
+        fs::read_dir(index_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|file_name| file_name.strip_suffix(".toml").map(String::from))
+            .filter(|stem| stem == crate_name || stem.starts_with(&prefix))
+            .filter_map(|stem| Self::load(index_dir, &stem))
+            .filter(|entry| entry.crate_name == crate_name)
+            .collect()
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        std::fs::write(
+            Self::path(index_dir, &binary_name(&self.crate_name, &self.profile)),
+            toml_edit::easy::to_string(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// When a profile directory in the shared cargo target directory was last used,
+/// stored in `~/.rust-exe/index/target/{dir}.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TargetEntry {
+    /// When cargo last built in the directory, in seconds since the unix epoch.
+    pub last_used: u64,
+}
+
+impl TargetEntry {
+    pub fn path(index_dir: &Path, dir: &str) -> PathBuf {
+        index_dir.join("target").join(format!("{dir}.toml"))
+    }
+
+    /// Loads a target directory's entry, returning `None` if it's missing or
+    /// unreadable.
+    pub fn load(index_dir: &Path, dir: &str) -> Option<Self> {
+        let toml = std::fs::read_to_string(Self::path(index_dir, dir)).ok()?;
+        toml_edit::easy::from_str(&toml).ok()
+    }
+
+    /// Records that cargo is building with `profile` now.
+    pub fn touch(index_dir: &Path, profile: &str) -> Result<()> {
+        let path = Self::path(index_dir, profile_dir(profile));
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let entry = TargetEntry {
+            last_used: now_seconds(),
+        };
+        std::fs::write(path, toml_edit::easy::to_string(&entry)?)?;
+        Ok(())
+    }
+}
+
+/// Returns the current time in seconds since the unix epoch.
+pub fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the path of the lock file for a crate's cached files.
+pub fn lock_path(index_dir: &Path, crate_name: &str) -> PathBuf {
+    index_dir.join(format!("{crate_name}.lock"))
+}
+
+/// How many times [`lock_crate`] tries to lock a crate before giving up.
+const LOCK_ATTEMPTS: u32 = 8;
+
+/// Takes a shared lock on a crate's cached files, which garbage collection
+/// won't remove while the returned file is open. Waits for any garbage
+/// collection of the crate that's in progress to finish first.
+pub fn lock_crate(index_dir: &Path, crate_name: &str) -> Result<File> {
+    let path = lock_path(index_dir, crate_name);
+    for attempt in 0..LOCK_ATTEMPTS {
+        let file = File::options().create(true).append(true).open(&path)?;
+        file.lock_shared()?;
+
+        // Garbage collection deletes lock files while holding them, so the
+        // lock only counts if the file we locked is still the one at `path`.
+        let inode = file.metadata()?.ino();
+        if fs::metadata(&path).map(|metadata| metadata.ino()).ok() == Some(inode) {
+            return Ok(file);
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(10 << attempt));
+    }
+    eyre::bail!(
+        "unable to lock {}, which kept being removed",
+        path.display()
+    )
+}
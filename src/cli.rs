@@ -45,19 +45,182 @@ pub fn main() -> eyre::Result<()> {
 
     trace!("{entry:#?}");
 
-    match entry.subcommand {
-        Subcommand::Help(_args) => help()?,
-        Subcommand::Run(mut args) => run(args.next_path().unwrap(), args.as_slice())?,
+    let mut options = entry.options;
+
+    let result = match entry.subcommand {
+        Subcommand::Fmt(mut args) => script_path(&mut args).and_then(|path| {
+            // Options can follow the path too, as in `rust fmt script.rs --check`.
+            for arg in args.as_slice() {
+                if !arg.as_bytes().starts_with(b"--") {
+                    eyre::bail!("unexpected argument: {:?}", arg.to_string_lossy());
+                }
+                options.push(arg.clone());
+            }
+            let check = take_option(&mut options, "check");
+            no_more_options(&options)?;
+            fmt(path, check)
+        }),
+        Subcommand::Install(mut args) => {
+            let list = take_option(&mut options, "list");
+            let name = take_option_value(&mut options, "name")?;
+            no_more_options(&options).and_then(|()| match (list, name) {
+                (true, None) => list_installed(),
+                (true, Some(_)) => eyre::bail!("--list can't be combined with --name"),
+                (false, name) => script_path(&mut args).and_then(|path| install(path, name)),
+            })
+        }
+        Subcommand::Cache(mut args) => {
+            let command = args.next_if(|arg| Some(arg.to_string_lossy().into_owned()));
+            // Anything after a `--` is an operand, even if it looks like an option.
+            let rest = args.as_slice();
+            let (rest, after_marker) = match rest.iter().position(|arg| arg == "--") {
+                Some(marker) => (&rest[..marker], &rest[marker + 1..]),
+                None => (rest, &[][..]),
+            };
+            let (flags, mut operands): (Vec<OsString>, Vec<OsString>) = rest
+                .iter()
+                .cloned()
+                .partition(|arg| arg.as_bytes().starts_with(b"--"));
+            operands.extend(after_marker.iter().cloned());
+            options.extend(flags);
+            let json = take_option(&mut options, "json");
+            let script = operands.first().map(PathBuf::from);
+            no_more_options(&options).and_then(|()| {
+                if let Some(extra) = operands.get(1) {
+                    eyre::bail!("unexpected argument: {:?}", extra.to_string_lossy());
+                }
+                match (command.as_deref(), script) {
+                    (Some("list"), None) => list_cache(json),
+                    (Some("path"), Some(script)) => print_cache_paths(&script, json),
+                    (Some("path"), None) => eyre::bail!("expected the path of a script"),
+                    (Some("clear"), script) => clear_cache(script.as_deref(), json),
+                    (Some("gc"), None) => cache_gc(),
+                    (Some("list" | "gc"), Some(script)) => {
+                        eyre::bail!("unexpected argument: {:?}", script.display().to_string())
+                    }
+                    (Some(command), _) => eyre::bail!("unrecognized cache subcommand: {command:?}"),
+                    (None, _) => {
+                        eyre::bail!("expected a cache subcommand: list, path, clear or gc")
+                    }
+                }
+            })
+        }
+        Subcommand::Help(_args) => help(),
+        Subcommand::Run(mut args) => {
+            let release = take_option(&mut options, "release");
+            let profile = take_option_value(&mut options, "profile")?;
+            no_more_options(&options).and_then(|()| {
+                let profile = match (release, profile) {
+                    (true, Some(_)) => eyre::bail!("--release can't be combined with --profile"),
+                    (true, None) => Some("release".to_string()),
+                    (false, profile) => profile,
+                };
+                script_path(&mut args).and_then(|path| run(path, profile, args.as_slice()))
+            })
+        }
+        _ if !options.is_empty() => no_more_options(&options),
+        Subcommand::Test(mut args) => {
+            script_path(&mut args).and_then(|path| test(path, args.as_slice()))
+        }
+        Subcommand::Check(mut args) => {
+            script_path(&mut args).and_then(|path| check(path, args.as_slice()))
+        }
+        Subcommand::Clippy(mut args) => {
+            script_path(&mut args).and_then(|path| clippy(path, args.as_slice()))
+        }
+        Subcommand::Fix(mut args) => {
+            script_path(&mut args).and_then(|path| fix(path, args.as_slice()))
+        }
+        Subcommand::Export(mut args) => script_path(&mut args).and_then(|path| {
+            let dir = args
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| eyre::eyre!("expected a directory to export the script to"))?;
+            export(path, dir)
+        }),
+        Subcommand::Uninstall(mut args) => match args.next() {
+            Some(name) => uninstall(&name.to_string_lossy()),
+            None => Err(eyre::eyre!("expected the name of an installed binary")),
+        },
         Subcommand::Eval(args) => eval(
             args.into_iter()
                 .map(|s| s.into_string().unwrap())
                 .collect::<Vec<_>>()
                 .join(" "),
             &[],
-        )?,
+        ),
+    };
+
+    if let Err(report) = &result {
+        if report.downcast_ref::<CompileError>().is_some() {
+            eprintln!("error: {report}");
+            std::process::exit(COMPILE_ERROR_EXIT_CODE);
+        }
+    }
+
+    result
+}
+
+/// Takes the path of the script that a subcommand operates on.
+fn script_path(args: &mut ArgStream) -> Result<PathBuf> {
+    args.next_path()
+        .ok_or_else(|| eyre::eyre!("expected the path of a script"))
+}
+
+/// Removes a subcommand-specific `--{name}` flag, returning whether it was given.
+fn take_option(options: &mut Vec<OsString>, name: &str) -> bool {
+    let count = options.len();
+    options.retain(|option| option.as_bytes().strip_prefix(b"--") != Some(name.as_bytes()));
+    options.len() != count
+}
+
+/// Removes a subcommand-specific `--{name}=value` option, returning its value.
+fn take_option_value(options: &mut Vec<OsString>, name: &str) -> Result<Option<String>> {
+    if take_option(options, name) {
+        eyre::bail!("--{name} requires a value");
+    }
+    let prefix = format!("--{name}=");
+    let index = match options
+        .iter()
+        .position(|option| option.as_bytes().starts_with(prefix.as_bytes()))
+    {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    let option = options.remove(index);
+    Ok(Some(option.to_string_lossy()[prefix.len()..].to_string()))
+}
+
+/// Long options that take a value, as either `--name=value` or `--name value`.
+static VALUE_OPTIONS: &[&str] = &["name", "profile"];
+
+/// Takes the options from the front of `args`, joining any of the
+/// [`VALUE_OPTIONS`] given as `--name value` into `--name=value`.
+fn next_options(args: &mut ArgStream) -> Vec<OsString> {
+    let mut options = Vec::new();
+    while let Some(mut option) = args.next_option() {
+        let takes_value = option.as_bytes().strip_prefix(b"--").is_some_and(|name| {
+            VALUE_OPTIONS
+                .iter()
+                .any(|value_option| value_option.as_bytes() == name)
+        });
+        if takes_value {
+            if let Some(value) = args.next_if(|arg| Some(arg.to_os_string())) {
+                option.push("=");
+                option.push(value);
+            }
+        }
+        options.push(option);
     }
+    options
+}
 
-    Ok(())
+/// Rejects any options that the subcommand didn't recognize.
+fn no_more_options(options: &[OsString]) -> Result<()> {
+    match options.first() {
+        Some(option) => eyre::bail!("unrecognized long argument: {:?}", option.to_string_lossy()),
+        None => Ok(()),
+    }
 }
 
 #[test]
@@ -65,6 +228,7 @@ fn test_cli_entry() {
     expect![[r#"
         CliEntry {
             verbosity: None,
+            options: [],
             subcommand: Run(
                 ArgStream {
                     args: [
@@ -99,6 +263,7 @@ fn test_cli_entry() {
             verbosity: Some(
                 -1,
             ),
+            options: [],
             subcommand: Run(
                 ArgStream {
                     args: [
@@ -123,17 +288,120 @@ fn test_cli_entry() {
         )
         .unwrap(),
     );
+
+    expect![[r#"
+        CliEntry {
+            verbosity: Some(
+                1,
+            ),
+            options: [
+                "--check",
+            ],
+            subcommand: Fmt(
+                ArgStream {
+                    args: [
+                        "fmt",
+                        "--check",
+                        "-v",
+                        "./hello.rs",
+                    ],
+                    offset: 3,
+                },
+            ),
+        }
+    "#]]
+    .assert_debug_eq(
+        &CliEntry::try_new(
+            ["fmt", "--check", "-v", "./hello.rs"]
+                .iter()
+                .map(Into::into)
+                .collect(),
+        )
+        .unwrap(),
+    );
+
+    expect![[r#"
+        CliEntry {
+            verbosity: None,
+            options: [
+                "--release",
+            ],
+            subcommand: Run(
+                ArgStream {
+                    args: [
+                        "run",
+                        "./hello.rs",
+                        "--to",
+                        "world",
+                    ],
+                    offset: 1,
+                },
+            ),
+        }
+    "#]]
+    .assert_debug_eq(
+        &CliEntry::try_new(
+            ["--release", "./hello.rs", "--to", "world"]
+                .iter()
+                .map(Into::into)
+                .collect(),
+        )
+        .unwrap(),
+    );
+
+    expect![[r#"
+        CliEntry {
+            verbosity: None,
+            options: [
+                "--profile=fast",
+                "--name=hi",
+            ],
+            subcommand: Install(
+                ArgStream {
+                    args: [
+                        "--profile",
+                        "fast",
+                        "install",
+                        "--name",
+                        "hi",
+                        "./hello.rs",
+                    ],
+                    offset: 5,
+                },
+            ),
+        }
+    "#]]
+    .assert_debug_eq(
+        &CliEntry::try_new(
+            ["--profile", "fast", "install", "--name", "hi", "./hello.rs"]
+                .iter()
+                .map(Into::into)
+                .collect(),
+        )
+        .unwrap(),
+    );
 }
 
 #[derive(Debug, Clone)]
 pub struct CliEntry {
     pub verbosity: Option<i32>,
+    /// Long options that aren't global, for the subcommand to interpret.
+    pub options: Vec<OsString>,
     pub subcommand: Subcommand,
 }
 
 #[derive(Debug, Clone)]
 pub enum Subcommand {
     Run(ArgStream),
+    Test(ArgStream),
+    Check(ArgStream),
+    Clippy(ArgStream),
+    Fmt(ArgStream),
+    Fix(ArgStream),
+    Export(ArgStream),
+    Install(ArgStream),
+    Uninstall(ArgStream),
+    Cache(ArgStream),
     Eval(ArgStream),
     Help(ArgStream),
 }
@@ -142,17 +410,18 @@ impl CliEntry {
     pub fn try_new(args: Vec<OsString>) -> eyre::Result<Self> {
         let mut args = ArgStream::new(args);
 
+        let mut verbosity: Option<i32> = None;
+        let mut subcommand_options = Vec::new();
+
+        let options_before_subcommand = next_options(&mut args);
+
         if args.peek_path().is_some() {
             args.push_front("run".into());
         }
 
-        let mut verbosity: Option<i32> = None;
-
-        let options_before_subcommand = args.next_options();
-
         let subcommand = args.next_subcommand().unwrap_or_else(|| "help".into());
 
-        let options_after_subcommand = args.next_options();
+        let options_after_subcommand = next_options(&mut args);
 
         let options = options_before_subcommand
             .into_iter()
@@ -168,7 +437,7 @@ impl CliEntry {
                         verbosity = Some(verbosity.unwrap_or(0) - 1);
                     }
                     _ => {
-                        eyre::bail!("unrecognized long argument: {:?}", option.to_string_lossy());
+                        subcommand_options.push(option);
                     }
                 }
             } else if let Some(option_bytes) = option.as_bytes().strip_prefix(b"-") {
@@ -195,8 +464,18 @@ impl CliEntry {
 
         Ok(CliEntry {
             verbosity,
+            options: subcommand_options,
             subcommand: match subcommand.as_bytes() {
                 b"run" => Subcommand::Run(args),
+                b"test" => Subcommand::Test(args),
+                b"check" => Subcommand::Check(args),
+                b"clippy" => Subcommand::Clippy(args),
+                b"fmt" => Subcommand::Fmt(args),
+                b"fix" => Subcommand::Fix(args),
+                b"export" => Subcommand::Export(args),
+                b"install" => Subcommand::Install(args),
+                b"uninstall" => Subcommand::Uninstall(args),
+                b"cache" => Subcommand::Cache(args),
                 b"eval" => Subcommand::Eval(args),
                 b"help" => Subcommand::Help(args),
                 _ => eyre::bail!(
@@ -0,0 +1,56 @@
+use {
+    crate::*,
+    ::eyre::WrapErr,
+    ::serde::Deserialize,
+    ::std::{collections::BTreeMap, io::ErrorKind, path::Path},
+};
+
+/// User settings, read from `~/.rust-exe/config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Config {
+    /// Features to enable when a script uses a path, in addition to the
+    /// built-in ones, like `"tokio::main" = ["macros", "rt-multi-thread"]`.
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Where `rust install` puts binaries, instead of `~/.local/bin`.
+    pub install_dir: Option<PathBuf>,
+    pub cache: CacheConfig,
+}
+
+/// Limits on the cache, in the `[cache]` table of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CacheConfig {
+    /// Cached crates and build directories that haven't been used for this
+    /// many days are removed.
+    pub max_age_days: u64,
+    /// When the cache is larger than this many megabytes, the least recently
+    /// used entries are removed until it isn't.
+    pub max_size_mb: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_age_days: 8,
+            max_size_mb: 4096,
+        }
+    }
+}
+
+impl Config {
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("config.toml")
+    }
+
+    /// Loads the config file, or the default configuration if there isn't one.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = Self::path(data_dir);
+        match std::fs::read_to_string(&path) {
+            Ok(toml) => toml_edit::easy::from_str(&toml)
+                .map_err(|err| eyre::eyre!("invalid config file {}: {err}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err).wrap_err_with(|| format!("failed to read {}", path.display())),
+        }
+    }
+}
@@ -1,10 +1,89 @@
-#![allow(unused)]
-use crate::*;
+use {
+    crate::*,
+    ::std::collections::{BTreeMap, BTreeSet},
+};
 
 /// The unix timestamp of the first crates.io index commit, rounded to noon UTC:
 /// 2014-11-04T12:00:00Z.
 static CRATES_EPOCH_SECONDS: u64 = 1_415_102_400;
 
+/// Crates that are provided by the toolchain, rather than by a registry.
+static BUILTIN_CRATES: &[&str] = &["core", "alloc", "std", "proc_macro", "test"];
+
+/// Path roots that refer to something other than a crate.
+static PATH_KEYWORDS: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Namespaces of tool attributes and lints (like `#[rustfmt::skip]` or
+/// `#![clippy::msrv = "1.60"]`), which aren't crates.
+static TOOL_NAMESPACES: &[&str] = &[
+    "clippy",
+    "diagnostic",
+    "miri",
+    "rust_analyzer",
+    "rustdoc",
+    "rustfmt",
+];
+
+/// Names from the standard prelude, and primitive types, which are commonly
+/// used as the first segment of a path (like `Vec::new` or `u8::MAX`).
+static PRELUDE_NAMES: &[&str] = &[
+    "AsMut",
+    "AsRef",
+    "Box",
+    "Clone",
+    "Copy",
+    "Default",
+    "DoubleEndedIterator",
+    "Drop",
+    "Eq",
+    "Err",
+    "ExactSizeIterator",
+    "Extend",
+    "Fn",
+    "FnMut",
+    "FnOnce",
+    "From",
+    "FromIterator",
+    "Into",
+    "IntoIterator",
+    "Iterator",
+    "None",
+    "Ok",
+    "Option",
+    "Ord",
+    "PartialEq",
+    "PartialOrd",
+    "Result",
+    "Send",
+    "Sized",
+    "Some",
+    "String",
+    "Sync",
+    "ToOwned",
+    "ToString",
+    "TryFrom",
+    "TryInto",
+    "Unpin",
+    "Vec",
+    "bool",
+    "char",
+    "f32",
+    "f64",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "isize",
+    "str",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "usize",
+];
+
 /*
 
 to process a file
@@ -24,3 +103,751 @@ to process a file
 
 maybe produce a git hash of the input tree, and the output tree.
 */
+
+/// A crate that a script depends on, keyed by the name the script uses for it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dependency {
+    /// The version requirement from the doc comment on the `use` or
+    /// `extern crate` item that brought the crate in, if there was one.
+    pub version: Option<semver::VersionReq>,
+    pub confidence: Confidence,
+    /// The name of the package providing the crate, if it's different from the
+    /// name the script uses for it.
+    pub package: Option<String>,
+    /// Every path into the crate that the script uses, like `tokio::fs::read`.
+    pub paths: BTreeSet<String>,
+}
+
+/// How sure we are that a name refers to an external crate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// The name is the first segment of an unqualified path (like
+    /// `serde_json::Value`), so it could also be a local item or module.
+    #[default]
+    Uncertain,
+    /// The name is from an `extern crate` item or a `::`-qualified path.
+    Confident,
+}
+
+/// A parsed Rust source file.
+///
+/// syn can't parse leading `::`s inside of `use` groups (like `use {::std::fmt};`),
+/// so those are blanked out of the source before it's parsed, and the locations
+/// of the use trees that followed them are recorded here instead.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub syntax: syn::File,
+    pub nested_roots: BTreeSet<(usize, usize)>,
+}
+
+/// Parses a Rust source file, including any leading `::`s in nested `use` groups.
+pub fn parse_source(source: &str) -> syn::Result<SourceFile> {
+    use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+
+    /// Finds leading `::`s in the `use` groups of a token stream, collecting the
+    /// locations of both colons and of the token following them.
+    fn find(tokens: TokenStream, in_use: bool, found: &mut Vec<[(usize, usize); 3]>) {
+        let location = |token: &TokenTree| {
+            let start = token.span().start();
+            (start.line, start.column)
+        };
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut in_use_item = false;
+        let mut at_tree_start = in_use;
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                TokenTree::Ident(ident) if !in_use && ident == "use" => {
+                    in_use_item = !matches!(
+                        tokens.get(index + 1),
+                        Some(TokenTree::Punct(punct)) if punct.as_char() == '<'
+                    );
+                }
+                TokenTree::Punct(punct) if !in_use && punct.as_char() == ';' => {
+                    in_use_item = false;
+                }
+                TokenTree::Group(group) => {
+                    let is_use_group =
+                        (in_use || in_use_item) && group.delimiter() == Delimiter::Brace;
+                    find(group.stream(), is_use_group, found);
+                }
+                TokenTree::Punct(first)
+                    if at_tree_start
+                        && first.as_char() == ':'
+                        && first.spacing() == Spacing::Joint =>
+                {
+                    if let (Some(second @ TokenTree::Punct(punct)), Some(next)) =
+                        (tokens.get(index + 1), tokens.get(index + 2))
+                    {
+                        if punct.as_char() == ':' {
+                            found.push([location(token), location(second), location(next)]);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            at_tree_start =
+                in_use && matches!(token, TokenTree::Punct(punct) if punct.as_char() == ',');
+        }
+    }
+
+    // The shebang line isn't valid Rust tokens, so we blank it out too.
+    let tokenizable = match source.split_once('\n') {
+        Some((first, rest)) if first.starts_with("#!") && !first.starts_with("#![") => {
+            format!("{}\n{rest}", " ".repeat(first.len()))
+        }
+        _ => source.to_string(),
+    };
+
+    let mut found = Vec::new();
+    if let Ok(tokens) = tokenizable.parse::<TokenStream>() {
+        find(tokens, false, &mut found);
+    }
+
+    let mut lines: Vec<Vec<char>> = source
+        .split('\n')
+        .map(|line| line.chars().collect())
+        .collect();
+    let mut nested_roots = BTreeSet::new();
+    for [first, second, next] in found {
+        for (line, column) in [first, second] {
+            lines[line - 1][column] = ' ';
+        }
+        nested_roots.insert(next);
+    }
+    let masked = lines
+        .into_iter()
+        .map(String::from_iter)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(SourceFile {
+        syntax: syn::parse_file(&masked)?,
+        nested_roots,
+    })
+}
+
+/// Identifies the external crates referenced from a script's parsed source files.
+///
+/// Uncertain candidates that are shadowed by a name defined in the script, or
+/// that are from the prelude, are left out. The rest still need to be checked
+/// against the registry with [`resolve_packages`].
+pub fn infer_dependencies<'ast>(
+    files: impl IntoIterator<Item = &'ast SourceFile>,
+) -> BTreeMap<String, Dependency> {
+    impl Visitor {
+        /// Inserts the crate at the root of `path`, recording the path.
+        fn insert(
+            &mut self,
+            path: String,
+            confidence: Confidence,
+            version: Option<&semver::VersionReq>,
+        ) {
+            let root_crate = path.split("::").next().unwrap_or_default().to_string();
+            let dependency = self.dependencies.entry(root_crate.clone()).or_default();
+            dependency.confidence = dependency.confidence.max(confidence);
+            dependency.paths.insert(path);
+            match (&dependency.version, version) {
+                (None, Some(version)) => dependency.version = Some(version.clone()),
+                (Some(existing), Some(version)) if existing != version => {
+                    warn!("ignoring version {version} for {root_crate}, already using {existing}");
+                }
+                _ => {}
+            }
+        }
+
+        fn is_nested_root(&self, span: proc_macro2::Span) -> bool {
+            let start = span.start();
+            self.nested_roots.contains(&(start.line, start.column))
+        }
+
+        /// Records a path into a crate that's already been inserted.
+        fn insert_path(&mut self, path: &[String]) {
+            if let Some(dependency) = path
+                .first()
+                .and_then(|root_crate| self.dependencies.get_mut(root_crate))
+            {
+                dependency.paths.insert(path.join("::"));
+            }
+        }
+
+        /// Inserts the crates named by a use tree, and records the local names
+        /// it binds. `root` is the confidence that the tree begins with a crate
+        /// name, or `None` if it continues the path of its `parent`. `prefix`
+        /// is the path from the crate root to the tree, if it's within a crate.
+        fn visit_use_tree_roots(
+            &mut self,
+            tree: &syn::UseTree,
+            root: Option<Confidence>,
+            prefix: &[String],
+            parent: Option<&syn::Ident>,
+            version: Option<&semver::VersionReq>,
+        ) {
+            let extend = |ident: &syn::Ident| -> Vec<String> {
+                if prefix.is_empty() || ident == "self" {
+                    prefix.to_vec()
+                } else {
+                    [prefix, &[ident.to_string()]].concat()
+                }
+            };
+
+            let root = match tree {
+                syn::UseTree::Path(syn::UsePath { ident, .. })
+                | syn::UseTree::Name(syn::UseName { ident, .. })
+                | syn::UseTree::Rename(syn::UseRename { ident, .. })
+                    if self.is_nested_root(ident.span()) =>
+                {
+                    Some(Confidence::Confident)
+                }
+                syn::UseTree::Group(group) if self.is_nested_root(group.brace_token.span) => {
+                    Some(Confidence::Confident)
+                }
+                _ => root,
+            };
+
+            match tree {
+                syn::UseTree::Path(syn::UsePath { ident, tree, .. }) => {
+                    let prefix = if let Some(confidence) = root {
+                        self.insert(ident.to_string(), confidence, version);
+                        vec![ident.to_string()]
+                    } else {
+                        extend(ident)
+                    };
+                    // `use a::{self}` only binds a new name if `a` isn't the crate.
+                    let parent = if root.is_some() { None } else { Some(ident) };
+                    self.visit_use_tree_roots(tree, None, &prefix, parent, version);
+                }
+                syn::UseTree::Name(syn::UseName { ident }) => {
+                    if let Some(confidence) = root {
+                        self.insert(ident.to_string(), confidence, version);
+                        return;
+                    }
+                    self.insert_path(&extend(ident));
+                    if ident != "self" {
+                        self.local_names.insert(ident.to_string());
+                    } else if let Some(parent) = parent {
+                        self.local_names.insert(parent.to_string());
+                    }
+                }
+                syn::UseTree::Rename(syn::UseRename { ident, rename, .. }) => {
+                    if let Some(confidence) = root {
+                        self.insert(ident.to_string(), confidence, version);
+                    } else {
+                        self.insert_path(&extend(ident));
+                    }
+                    if rename != "_" && rename != ident {
+                        self.local_names.insert(rename.to_string());
+                    }
+                }
+                syn::UseTree::Group(group) => {
+                    for tree in group.items.iter() {
+                        self.visit_use_tree_roots(tree, root, prefix, parent, version);
+                    }
+                }
+                syn::UseTree::Glob(_) => self.insert_path(prefix),
+            }
+        }
+
+        fn insert_local_item(&mut self, item: &syn::Item) {
+            let ident = match item {
+                syn::Item::Const(item) => &item.ident,
+                syn::Item::Enum(item) => &item.ident,
+                syn::Item::Fn(item) => &item.sig.ident,
+                syn::Item::Mod(item) => &item.ident,
+                syn::Item::Static(item) => &item.ident,
+                syn::Item::Struct(item) => &item.ident,
+                syn::Item::Trait(item) => &item.ident,
+                syn::Item::TraitAlias(item) => &item.ident,
+                syn::Item::Type(item) => &item.ident,
+                syn::Item::Union(item) => &item.ident,
+                _ => return,
+            };
+            self.local_names.insert(ident.to_string());
+        }
+    }
+
+    impl<'ast> syn::visit::Visit<'ast> for Visitor {
+        fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+            for path in leading_colon_paths(mac.tokens.clone()) {
+                self.insert(path, Confidence::Confident, None);
+            }
+            syn::visit::visit_macro(self, mac);
+        }
+
+        fn visit_attribute(&mut self, attr: &'ast syn::Attribute) {
+            for path in leading_colon_paths(attr.tokens.clone()) {
+                self.insert(path, Confidence::Confident, None);
+            }
+            syn::visit::visit_attribute(self, attr);
+        }
+
+        fn visit_path(&mut self, path: &'ast syn::Path) {
+            let full_path = path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::");
+            if path.leading_colon.is_some() {
+                self.insert(full_path, Confidence::Confident, None);
+            } else if path.segments.len() > 1 {
+                self.insert(full_path, Confidence::Uncertain, None);
+            }
+            syn::visit::visit_path(self, path);
+        }
+
+        fn visit_item(&mut self, item: &'ast syn::Item) {
+            self.insert_local_item(item);
+            syn::visit::visit_item(self, item);
+        }
+
+        fn visit_type_param(&mut self, param: &'ast syn::TypeParam) {
+            self.local_names.insert(param.ident.to_string());
+            syn::visit::visit_type_param(self, param);
+        }
+
+        fn visit_item_extern_crate(&mut self, item: &'ast syn::ItemExternCrate) {
+            if let Some((_, rename)) = &item.rename {
+                if rename != &item.ident {
+                    self.aliases.insert(rename.to_string());
+                }
+            }
+            if item.ident != "self" {
+                let version = doc_version(&item.attrs);
+                self.insert(
+                    item.ident.to_string(),
+                    Confidence::Confident,
+                    version.as_ref(),
+                );
+            }
+            syn::visit::visit_item_extern_crate(self, item);
+        }
+
+        fn visit_item_use(&mut self, item_use: &'ast syn::ItemUse) {
+            let version = doc_version(&item_use.attrs);
+            let confidence = if item_use.leading_colon.is_some() {
+                Confidence::Confident
+            } else {
+                Confidence::Uncertain
+            };
+            self.visit_use_tree_roots(
+                &item_use.tree,
+                Some(confidence),
+                &[],
+                None,
+                version.as_ref(),
+            );
+            syn::visit::visit_item_use(self, item_use);
+        }
+    }
+
+    #[derive(Default)]
+    struct Visitor {
+        dependencies: BTreeMap<String, Dependency>,
+        /// Names given to crates by `extern crate ... as ...;`.
+        aliases: BTreeSet<String>,
+        /// Names of items, imports and type parameters defined in the script,
+        /// which may shadow uncertain candidates.
+        local_names: BTreeSet<String>,
+        /// The nested roots of the file currently being visited.
+        nested_roots: BTreeSet<(usize, usize)>,
+    }
+
+    let mut visitor = Visitor::default();
+    for file in files {
+        visitor.nested_roots = file.nested_roots.clone();
+        syn::visit::visit_file(&mut visitor, &file.syntax);
+    }
+
+    let aliases = visitor.aliases;
+    let local_names = visitor.local_names;
+    visitor
+        .dependencies
+        .into_iter()
+        .filter(|(name, _)| !BUILTIN_CRATES.contains(&name.as_str()))
+        .filter(|(name, _)| !PATH_KEYWORDS.contains(&name.as_str()))
+        .filter(|(name, _)| !TOOL_NAMESPACES.contains(&name.as_str()))
+        .filter(|(name, _)| !aliases.contains(name))
+        .filter(|(name, dependency)| {
+            if dependency.confidence == Confidence::Confident {
+                true
+            } else if local_names.contains(name) {
+                debug!("rejecting uncertain dependency {name}: it's defined in the script");
+                false
+            } else if PRELUDE_NAMES.contains(&name.as_str()) {
+                debug!("rejecting uncertain dependency {name}: it's from the prelude");
+                false
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+/// Looks up the package providing each dependency in the registry index,
+/// dropping uncertain candidates that don't resolve to a real crate.
+///
+/// Dependencies without a version requirement are limited to versions no newer
+/// than the latest one published at `timestamp` (in seconds since the unix
+/// epoch, but no earlier than the first crates.io release), so that old scripts
+/// keep building against the versions they were written for.
+pub fn resolve_packages(
+    dependencies: BTreeMap<String, Dependency>,
+    registry: &Registry,
+    timestamp: u64,
+) -> BTreeMap<String, Dependency> {
+    let timestamp = timestamp.max(CRATES_EPOCH_SECONDS);
+
+    dependencies
+        .into_iter()
+        .filter_map(|(name, mut dependency)| {
+            let package = registry.package_name(&name);
+            match (dependency.confidence, &package) {
+                (Confidence::Uncertain, Some(package)) if package.replace('-', "_") == name => {
+                    debug!("accepting uncertain dependency {name} as package {package}");
+                }
+                (Confidence::Uncertain, _) => {
+                    debug!("rejecting uncertain dependency {name}: no such crate in the registry index");
+                    return None;
+                }
+                (Confidence::Confident, None) => {
+                    debug!("{name} isn't in the local registry index, using it as-is");
+                }
+                (Confidence::Confident, Some(_)) => {}
+            }
+            if dependency.version.is_none() {
+                let versions = registry.versions(package.as_deref().unwrap_or(&name));
+                match latest_published_before(&versions, &semver::VersionReq::STAR, timestamp) {
+                    Some(latest) => {
+                        debug!("resolved {name} to {} as of {timestamp}", latest.vers);
+                        dependency.version = Some(compatible_up_to(&latest.vers));
+                    }
+                    // The lockfile pass warns about these, along with any of
+                    // their dependencies that are undated too.
+                    None if has_undated_versions(&versions, &semver::VersionReq::STAR) => {
+                        debug!("some versions of {name} aren't dated in the registry index");
+                    }
+                    None => {}
+                }
+            }
+
+            dependency.package = package.filter(|package| *package != name);
+            Some((name, dependency))
+        })
+        .collect()
+}
+
+/// Returns a requirement matching the versions that are semver-compatible with
+/// `version` but no newer than it, like `>=1.0.0, <=1.0.137`.
+pub fn compatible_up_to(version: &semver::Version) -> semver::VersionReq {
+    let lower = match (version.major, version.minor) {
+        (0, 0) => format!("0.0.{}", version.patch),
+        (0, minor) => format!("0.{minor}.0"),
+        (major, _) => format!("{major}.0.0"),
+    };
+    format!(">={lower}, <={version}").parse().unwrap()
+}
+
+/// Finds any leading-colon paths (like `::base64::encode`) in an unparsed token
+/// stream, such as the body of a macro invocation, without the leading colons.
+pub fn leading_colon_paths(tokens: proc_macro2::TokenStream) -> Vec<String> {
+    use proc_macro2::{Spacing, TokenTree};
+
+    /// Keywords that may directly precede a leading-colon path in an expression.
+    static KEYWORDS: &[&str] = &[
+        "as", "box", "break", "else", "if", "in", "let", "match", "move", "mut", "return", "yield",
+    ];
+
+    /// Returns the identifier following the `::` at `index`, if there is one.
+    fn segment_after(tokens: &[TokenTree], index: usize) -> Option<String> {
+        match (
+            tokens.get(index),
+            tokens.get(index + 1),
+            tokens.get(index + 2),
+        ) {
+            (
+                Some(TokenTree::Punct(first)),
+                Some(TokenTree::Punct(second)),
+                Some(TokenTree::Ident(ident)),
+            ) if first.as_char() == ':'
+                && first.spacing() == Spacing::Joint
+                && second.as_char() == ':' =>
+            {
+                Some(ident.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    let mut paths = Vec::new();
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Group(group) => paths.extend(leading_colon_paths(group.stream())),
+            TokenTree::Punct(_) => {
+                let is_leading = match index.checked_sub(1).map(|index| &tokens[index]) {
+                    None => true,
+                    Some(TokenTree::Ident(ident)) => KEYWORDS.contains(&ident.to_string().as_str()),
+                    Some(TokenTree::Punct(punct)) => !matches!(punct.as_char(), '>' | '$'),
+                    Some(TokenTree::Group(_) | TokenTree::Literal(_)) => false,
+                };
+                if !is_leading {
+                    continue;
+                }
+                let mut segments = Vec::new();
+                while let Some(segment) = segment_after(&tokens, index + 3 * segments.len()) {
+                    segments.push(segment);
+                }
+                if !segments.is_empty() {
+                    paths.push(segments.join("::"));
+                }
+            }
+            _ => {}
+        }
+    }
+    paths
+}
+
+/// Returns the version requirement from an item's doc comment, taken from the
+/// first paragraph that consists of nothing but a version requirement, such as
+/// `/// 1.0.137` or `/// >=0.4, <0.6`. Prose and code blocks are ignored.
+pub fn doc_version(attrs: &[syn::Attribute]) -> Option<semver::VersionReq> {
+    let lines = attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                lit: syn::Lit::Str(lit),
+                ..
+            })) => Some(lit.value()),
+            _ => None,
+        })
+        .flat_map(|doc| {
+            doc.split('\n')
+                .map(|line| line.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut in_code_block = false;
+    for (index, line) in lines.iter().enumerate() {
+        if line.starts_with("```") || line.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        let previous = index.checked_sub(1).and_then(|index| lines.get(index));
+        let next = lines.get(index + 1);
+        let own_paragraph =
+            previous.is_none_or(|line| line.is_empty()) && next.is_none_or(|line| line.is_empty());
+        if in_code_block || !own_paragraph {
+            continue;
+        }
+        if let Ok(version) = line.parse() {
+            return Some(version);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_infer_dependencies() {
+    let file = parse_source(
+        r#"
+        /// 1.0.137
+        use ::serde;
+        /// Error handling.
+        ///
+        /// ~0.6
+        extern crate eyre;
+        use ::once_cell::sync::OnceCell;
+        /// 0.13
+        use ::std::fmt;
+
+        #[derive(::serde::Serialize)]
+        struct Unit;
+
+        fn main() {
+            let _ = ::base64::encode("");
+            println!("{}", ::hex::encode([1, 2, 3]));
+            let _ = vec![::uuid::Uuid::new_v4(), <Vec<u8>>::new(), String::new()];
+            let _ = matches!(1, x if x == ::rand::random());
+            let _ = dbg!({ let buffer: ::itoa::Buffer = todo!(); buffer });
+        }
+
+        use serde_json::Value;
+        use std::io;
+        mod helpers {}
+
+        #[tokio::main]
+        async fn uncertain<T: Default>() {
+            let _ = regex::Regex::new("");
+            let _ = (Vec::<u8>::new(), T::default(), u8::MAX, Self::X);
+            let _ = (io::stdout(), helpers::f(), Value::Null, Unit::default());
+        }
+        "#,
+    )
+    .unwrap();
+
+    expect![[r#"
+        {
+            "base64": (
+                None,
+                Confident,
+            ),
+            "eyre": (
+                Some(
+                    "~0.6",
+                ),
+                Confident,
+            ),
+            "hex": (
+                None,
+                Confident,
+            ),
+            "itoa": (
+                None,
+                Confident,
+            ),
+            "once_cell": (
+                None,
+                Confident,
+            ),
+            "rand": (
+                None,
+                Confident,
+            ),
+            "regex": (
+                None,
+                Uncertain,
+            ),
+            "serde": (
+                Some(
+                    "^1.0.137",
+                ),
+                Confident,
+            ),
+            "serde_json": (
+                None,
+                Uncertain,
+            ),
+            "tokio": (
+                None,
+                Uncertain,
+            ),
+            "uuid": (
+                None,
+                Confident,
+            ),
+        }
+    "#]]
+    .assert_debug_eq(
+        &infer_dependencies([&file])
+            .into_iter()
+            .map(|(name, dependency)| {
+                (
+                    name,
+                    (
+                        dependency.version.map(|v| v.to_string()),
+                        dependency.confidence,
+                    ),
+                )
+            })
+            .collect::<BTreeMap<_, _>>(),
+    );
+}
+
+#[test]
+fn test_compatible_up_to() {
+    let requirement = |version: &str| compatible_up_to(&version.parse().unwrap()).to_string();
+    assert_eq!(requirement("1.0.137"), ">=1.0.0, <=1.0.137");
+    assert_eq!(requirement("0.6.8"), ">=0.6.0, <=0.6.8");
+    assert_eq!(requirement("0.0.3"), ">=0.0.3, <=0.0.3");
+}
+
+#[test]
+fn test_doc_version() {
+    let version = |source: &str| {
+        let item: syn::ItemUse = syn::parse_str(source).unwrap();
+        doc_version(&item.attrs).map(|version| version.to_string())
+    };
+    assert_eq!(
+        version("/// 1.0.137\nuse ::serde;"),
+        Some("^1.0.137".into())
+    );
+    assert_eq!(
+        version("/// Error handling.\n///\n/// >=0.4, <0.6\nuse ::eyre;"),
+        Some(">=0.4, <0.6".into())
+    );
+    assert_eq!(
+        version("/** Time.\n\n ~0.4\n*/\nuse ::chrono;"),
+        Some("~0.4".into())
+    );
+    assert_eq!(
+        version("/// Parses version\n/// 2\n/// of the format.\nuse ::a;"),
+        None
+    );
+    assert_eq!(version("/// Steps:\n/// 1.0\nuse ::a;"), None);
+    assert_eq!(
+        version("/// Example:\n///\n/// ```\n/// 1.0\n/// ```\nuse ::a;"),
+        None
+    );
+    assert_eq!(version("/// Version 2 of the API.\nuse ::a;"), None);
+}
+
+#[test]
+fn test_use_tree_roots() {
+    let cases: &[(&str, &[&str])] = &[
+        ("use ::a;", &["a"]),
+        ("use ::a::b::{c, d};", &["a"]),
+        ("use ::a as _;", &["a"]),
+        ("use ::{a, b::c, d as e};", &["a", "b", "d"]),
+        ("use ::{a::{b, c::*}, d::*};", &["a", "d"]),
+        ("use ::{{a}, {{b::c}}, *};", &["a", "b"]),
+        ("use {::a, ::b::c};", &["a", "b"]),
+        ("use {a::b, ::c};", &["a?", "c"]),
+        (
+            "use {{{::{{{{crossterm::style::{{{{Stylize}}}}}}}}}}};",
+            &["crossterm"],
+        ),
+        ("use {::{a::*, b as c}, d::{e}};", &["a", "b", "d?"]),
+        ("pub(crate) use ::{a::*};", &["a"]),
+        ("fn f() { use {::a::b}; }", &["a"]),
+        ("mod m { use { ::a, ::{ b } }; }", &["a", "b"]),
+        (
+            "use self::a; use crate::b; use c::d; use super::*;",
+            &["c?"],
+        ),
+        (
+            "use a::{self, b}; use c as d; fn f() { a::x(); d::y(); e::z(); }",
+            &["a?", "c?", "e?"],
+        ),
+        (
+            "mod a { pub use b::{self as c}; } fn f<T>() { a::x(); c::y(); T::z(); }",
+            &["b?"],
+        ),
+        ("use ::std::fmt; use ::{core, alloc::vec};", &[]),
+        ("extern crate a;", &["a"]),
+        (
+            "extern crate a as b; use ::b::c; fn f() { ::b::d(); }",
+            &["a"],
+        ),
+        ("extern crate a as a; use ::a::b;", &["a"]),
+        ("extern crate self as me; use ::me::x;", &[]),
+        ("#!/usr/bin/env rust\nuse {::a};", &["a"]),
+        (
+            "#![clippy::msrv = \"1.60\"] #[rustfmt::skip] #[allow(clippy::all)] fn f() { a::b(); }",
+            &["a?"],
+        ),
+    ];
+
+    for (source, expected) in cases {
+        let file = parse_source(source).unwrap();
+        let actual: Vec<String> = infer_dependencies([&file])
+            .into_iter()
+            .map(|(name, dependency)| match dependency.confidence {
+                Confidence::Confident => name,
+                Confidence::Uncertain => format!("{name}?"),
+            })
+            .collect();
+        assert_eq!(actual, *expected, "for {source:?}");
+    }
+}
@@ -0,0 +1,291 @@
+use {
+    crate::*,
+    ::once_cell::sync::Lazy,
+    ::regex::Regex,
+    ::std::{
+        io::{BufRead, BufReader, IsTerminal, Read, Write},
+        path::Path,
+        process::{Command, ExitStatus, Stdio},
+    },
+};
+
+/// Maps the files of a generated crate back to the script files they were
+/// copied from. Line and column numbers are preserved when we generate the
+/// crate, so only the paths need to be mapped.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// The generated crate's directory, which absolute paths in backtraces
+    /// start with.
+    crate_dir: PathBuf,
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl SourceMap {
+    pub fn new(crate_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            crate_dir: crate_dir.into(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Records that `generated`, relative to the generated crate's directory,
+    /// is a copy of `original`.
+    pub fn insert(&mut self, generated: impl Into<PathBuf>, original: impl Into<PathBuf>) {
+        self.files.push((generated.into(), original.into()));
+    }
+
+    /// Returns the original path of a file in the generated crate, if it's one
+    /// of the script's files.
+    pub fn original(&self, generated: &Path) -> Option<&Path> {
+        self.files
+            .iter()
+            .find(|(path, _)| path == generated)
+            .map(|(_, original)| original.as_path())
+    }
+
+    /// Rewrites the locations of a rendered diagnostic (`--> file:line:col`
+    /// and `::: file:line:col`) to refer to the original files.
+    pub fn remap_rendered(&self, rendered: &str) -> String {
+        static LOCATION: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(
+                r"(?mx)
+                ^(?P<prefix>[\x20\t]*(?:\x1b\[[0-9;]*m)*(?:-->|:::)\x20(?:\x1b\[[0-9;]*m)*)
+                (?P<path>[^\s\x1b]+?):(?P<line>\d+):(?P<column>\d+)",
+            )
+            .unwrap()
+        });
+        LOCATION
+            .replace_all(rendered, |captures: &regex::Captures| {
+                let path = Path::new(&captures["path"]);
+                let path = self.original(path).unwrap_or(path);
+                format!(
+                    "{}{}:{}:{}",
+                    &captures["prefix"],
+                    path.display(),
+                    &captures["line"],
+                    &captures["column"]
+                )
+            })
+            .into_owned()
+    }
+
+    /// Rewrites the locations in panic messages (`panicked at file:line:col`)
+    /// and backtrace frames (`at /path/to/file:line:col`), such as those in
+    /// test output, to refer to the original files.
+    pub fn remap_panics(&self, output: &str) -> String {
+        static PANIC: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(
+                r"(?mx)
+                (?P<prefix>panicked\x20at\x20(?:'.*',\x20)?|^\s+at\x20)
+                (?P<path>[^\s:']+):(?P<line>\d+):(?P<column>\d+)",
+            )
+            .unwrap()
+        });
+        PANIC
+            .replace_all(output, |captures: &regex::Captures| {
+                let path = Path::new(&captures["path"]);
+                let generated = path.strip_prefix(&self.crate_dir).unwrap_or(path);
+                let path = self.original(generated).unwrap_or(path);
+                format!(
+                    "{}{}:{}:{}",
+                    &captures["prefix"],
+                    path.display(),
+                    &captures["line"],
+                    &captures["column"]
+                )
+            })
+            .into_owned()
+    }
+
+    /// Rewrites `file_name`s in the spans of a JSON compiler message, in place.
+    pub fn remap_message(&self, message: &mut serde_json::Value) {
+        if let Some(spans) = message["spans"].as_array_mut() {
+            for span in spans {
+                if let Some(original) = span["file_name"]
+                    .as_str()
+                    .and_then(|file_name| self.original(Path::new(file_name)))
+                {
+                    span["file_name"] = original.to_string_lossy().into_owned().into();
+                }
+            }
+        }
+        if let Some(children) = message["children"].as_array_mut() {
+            for child in children {
+                self.remap_message(child);
+            }
+        }
+        if let Some(rendered) = message["rendered"].as_str() {
+            message["rendered"] = self.remap_rendered(rendered).into();
+        }
+    }
+}
+
+/// The result of a cargo command run with [`run_cargo`].
+#[derive(Debug, Clone)]
+pub struct CargoOutput {
+    pub status: ExitStatus,
+    /// The paths of any test executables that cargo built.
+    pub executables: Vec<PathBuf>,
+}
+
+/// Runs a cargo command with JSON diagnostics, printing each compiler message
+/// to stderr with its locations mapped back to the original script files. Any
+/// other output is passed through.
+pub fn run_cargo(command: &mut Command, source_map: &SourceMap) -> Result<CargoOutput> {
+    run_cargo_with(command, &[], source_map)
+}
+
+/// Like [`run_cargo`], with `extra_args` following the message format option,
+/// so that they may include a `--` and arguments for the underlying tool.
+pub fn run_cargo_with(
+    command: &mut Command,
+    extra_args: &[OsString],
+    source_map: &SourceMap,
+) -> Result<CargoOutput> {
+    let message_format = if std::io::stderr().is_terminal() {
+        "--message-format=json-diagnostic-rendered-ansi"
+    } else {
+        "--message-format=json"
+    };
+
+    let mut child = command
+        .arg(message_format)
+        .args(extra_args)
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut executables = Vec::new();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    for line in stdout.lines() {
+        let line = line?;
+        match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(mut json) if json["reason"].is_string() => {
+                if json["reason"] == "compiler-message" {
+                    let message = &mut json["message"];
+                    source_map.remap_message(message);
+                    if let Some(rendered) = message["rendered"].as_str() {
+                        eprint!("{rendered}");
+                    }
+                } else if json["reason"] == "compiler-artifact" && json["profile"]["test"] == true {
+                    if let Some(executable) = json["executable"].as_str() {
+                        executables.push(PathBuf::from(executable));
+                    }
+                }
+            }
+            _ => {
+                let mut stdout = std::io::stdout().lock();
+                writeln!(stdout, "{line}")?;
+            }
+        }
+    }
+
+    Ok(CargoOutput {
+        status: child.wait()?,
+        executables,
+    })
+}
+
+/// Runs a command, such as a test executable, passing its stdout and stderr
+/// through with the locations in any panic messages mapped back to the original
+/// files.
+pub fn run_remapped(command: &mut Command, source_map: &SourceMap) -> Result<ExitStatus> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    std::thread::scope(|scope| {
+        let stderr = scope.spawn(|| remap_lines(stderr, std::io::stderr(), source_map));
+        remap_lines(stdout, std::io::stdout(), source_map)?;
+        stderr.join().unwrap()
+    })?;
+
+    Ok(child.wait()?)
+}
+
+/// Copies `reader` to `writer` line by line, remapping panic locations in any
+/// lines that are valid UTF-8.
+fn remap_lines(reader: impl Read, mut writer: impl Write, source_map: &SourceMap) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        match std::str::from_utf8(&line) {
+            Ok(text) => writer.write_all(source_map.remap_panics(text).as_bytes())?,
+            Err(_) => writer.write_all(&line)?,
+        }
+        writer.flush()?;
+        line.clear();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_remap_message() {
+    let mut source_map = SourceMap::default();
+    source_map.insert("hello.rs", "examples/Hello.rs");
+    source_map.insert("multi/helpers/mod.rs", "../multi/helpers/mod.rs");
+
+    let mut message: serde_json::Value = serde_json::from_str(
+        r#"{
+            "rendered": "error[E0308]: mismatched types\n --> hello.rs:3:18\n  |\n3 |     let x: u32 = \"nope\";\n  |            ---   ^^^^^^ expected `u32`, found `&str`\n\n  ::: multi/helpers/mod.rs:1:1\n  ::: /registry/src/lib.rs:9:9\n",
+            "spans": [
+                {"file_name": "hello.rs", "line_start": 3, "column_start": 18},
+                {"file_name": "/registry/src/lib.rs", "line_start": 9, "column_start": 9}
+            ],
+            "children": [
+                {"rendered": null, "spans": [{"file_name": "multi/helpers/mod.rs"}], "children": []}
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    source_map.remap_message(&mut message);
+
+    expect![[r#"
+        error[E0308]: mismatched types
+         --> examples/Hello.rs:3:18
+          |
+        3 |     let x: u32 = "nope";
+          |            ---   ^^^^^^ expected `u32`, found `&str`
+
+          ::: ../multi/helpers/mod.rs:1:1
+          ::: /registry/src/lib.rs:9:9
+    "#]]
+    .assert_eq(message["rendered"].as_str().unwrap());
+    assert_eq!(message["spans"][0]["file_name"], "examples/Hello.rs");
+    assert_eq!(message["spans"][1]["file_name"], "/registry/src/lib.rs");
+    assert_eq!(
+        message["children"][0]["spans"][0]["file_name"],
+        "../multi/helpers/mod.rs"
+    );
+
+    assert_eq!(
+        source_map.remap_panics("thread 'tests::fails' panicked at hello.rs:7:9:"),
+        "thread 'tests::fails' panicked at examples/Hello.rs:7:9:"
+    );
+    assert_eq!(
+        source_map.remap_panics("thread 'main' panicked at 'oops', multi/helpers/mod.rs:2:5"),
+        "thread 'main' panicked at 'oops', ../multi/helpers/mod.rs:2:5"
+    );
+
+    let mut source_map = SourceMap::new("/home/me/.rust-exe/src/hello-1234");
+    source_map.insert("hello.rs", "examples/Hello.rs");
+    assert_eq!(
+        source_map.remap_panics(
+            "   2: hello::fails\n             at /home/me/.rust-exe/src/hello-1234/hello.rs:7:9\n"
+        ),
+        "   2: hello::fails\n             at examples/Hello.rs:7:9\n"
+    );
+    assert_eq!(
+        source_map.remap_panics("             at /rustc/abc/library/core/src/panicking.rs:80:14"),
+        "             at /rustc/abc/library/core/src/panicking.rs:80:14"
+    );
+
+    let colored = "\u{1b}[0m\u{1b}[1m\u{1b}[38;5;12m--> \u{1b}[0m\u{1b}[0mhello.rs:3:18\u{1b}[0m";
+    assert_eq!(
+        source_map.remap_rendered(colored),
+        "\u{1b}[0m\u{1b}[1m\u{1b}[38;5;12m--> \u{1b}[0m\u{1b}[0mexamples/Hello.rs:3:18\u{1b}[0m"
+    );
+}
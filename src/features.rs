@@ -0,0 +1,122 @@
+use {crate::*, ::std::collections::BTreeSet};
+
+/// Features that crates need enabled for some of their paths to be available,
+/// as `(path, features)`. A path also covers everything within it.
+static FEATURES: &[(&str, &[&str])] = &[
+    ("clap::Args", &["derive"]),
+    ("clap::Parser", &["derive"]),
+    ("clap::Subcommand", &["derive"]),
+    ("clap::ValueEnum", &["derive"]),
+    ("reqwest::blocking", &["blocking"]),
+    ("serde::Deserialize", &["derive"]),
+    ("serde::Serialize", &["derive"]),
+    ("serde_json::value::RawValue", &["raw_value"]),
+    ("strum::Display", &["derive"]),
+    ("strum::EnumIter", &["derive"]),
+    ("strum::EnumString", &["derive"]),
+    ("syn::fold", &["fold", "full"]),
+    ("syn::visit", &["visit", "full"]),
+    ("syn::visit_mut", &["visit-mut", "full"]),
+    ("tokio::fs", &["fs"]),
+    ("tokio::io", &["io-util", "io-std"]),
+    ("tokio::join", &["macros"]),
+    ("tokio::main", &["macros", "rt-multi-thread"]),
+    ("tokio::net", &["net"]),
+    ("tokio::process", &["process"]),
+    ("tokio::select", &["macros"]),
+    ("tokio::signal", &["signal"]),
+    ("tokio::spawn", &["rt"]),
+    ("tokio::sync", &["sync"]),
+    ("tokio::task", &["rt"]),
+    ("tokio::test", &["macros", "rt"]),
+    ("tokio::time", &["time"]),
+    ("tokio::try_join", &["macros"]),
+    ("tracing_subscriber::EnvFilter", &["env-filter"]),
+    ("tracing_subscriber::filter::EnvFilter", &["env-filter"]),
+    ("uuid::Uuid::new_v4", &["v4"]),
+];
+
+/// Returns the features a dependency needs for the paths the script uses from
+/// it, according to the built-in table and the user's configuration.
+pub fn required_features(dependency: &Dependency, config: &Config) -> BTreeSet<String> {
+    let uses = |prefix: &str| {
+        dependency.paths.iter().any(|path| {
+            path.strip_prefix(prefix)
+                .map(|rest| rest.is_empty() || rest.starts_with("::"))
+                .unwrap_or(false)
+        })
+    };
+
+    let mut required = BTreeSet::new();
+    for (prefix, features) in FEATURES {
+        if uses(prefix) {
+            required.extend(features.iter().map(|feature| feature.to_string()));
+        }
+    }
+    for (prefix, features) in config.features.iter() {
+        if uses(prefix) {
+            required.extend(features.iter().cloned());
+        }
+    }
+    required
+}
+
+#[test]
+fn test_required_features() {
+    let files = [parse_source(
+        r#"
+            use ::serde::{Deserialize, Serialize as _};
+            use ::tokio::io::AsyncReadExt;
+
+            #[derive(::serde::Serialize)]
+            struct Unit;
+
+            #[::tokio::main]
+            async fn main() {
+                let _ = ::tokio::fs::read("").await;
+                let _ = ::tokio::timeout;
+                let _ = ::syn::visitor;
+            }
+            "#,
+    )
+    .unwrap()];
+    let dependencies = infer_dependencies(&files);
+
+    let config = Config {
+        features: [("syn::visitor".to_string(), vec!["extra-traits".to_string()])].into(),
+        ..Config::default()
+    };
+
+    expect![[r#"
+        [
+            (
+                "serde",
+                {
+                    "derive",
+                },
+            ),
+            (
+                "syn",
+                {
+                    "extra-traits",
+                },
+            ),
+            (
+                "tokio",
+                {
+                    "fs",
+                    "io-std",
+                    "io-util",
+                    "macros",
+                    "rt-multi-thread",
+                },
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(
+        &dependencies
+            .iter()
+            .map(|(name, dependency)| (name, required_features(dependency, &config)))
+            .collect::<Vec<_>>(),
+    );
+}
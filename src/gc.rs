@@ -0,0 +1,599 @@
+use {
+    crate::*,
+    ::eyre::WrapErr,
+    ::serde::Serialize,
+    ::std::{
+        collections::{BTreeMap, BTreeSet},
+        fs::{self, File, TryLockError},
+        io::ErrorKind,
+        path::Path,
+        process::{Command, Stdio},
+    },
+};
+
+/// How long to wait between garbage collections started in the background.
+const GC_INTERVAL_SECONDS: u64 = 60 * 60;
+
+/// A set of cached files that are used, and removed, together: a generated
+/// crate with its binaries and index entries, or one of the profile
+/// directories in the shared cargo target directory.
+#[derive(Debug, Clone)]
+pub struct CacheItem {
+    /// The crate name, or `target/{profile}` for a build directory.
+    pub name: String,
+    /// The path of the script that the crate was generated for, if known.
+    pub source_path: Option<PathBuf>,
+    /// The git hash of the script's source files when it was last built.
+    pub content_hash: Option<String>,
+    pub paths: Vec<PathBuf>,
+    /// The compiled binaries among `paths`.
+    pub binaries: Vec<PathBuf>,
+    /// The file that's locked while the item is in use. For build directories,
+    /// this is the lock file that cargo holds while building.
+    pub lock_path: PathBuf,
+    /// When the item was last used, in seconds since the unix epoch.
+    pub last_used: u64,
+    /// The total size of the item's files, in bytes.
+    pub size: u64,
+}
+
+/// Lists everything in the cache, least recently used first.
+pub fn cache_items(dirs: &DataDirs) -> Result<Vec<CacheItem>> {
+    let mut crates = BTreeMap::new();
+
+    let mut binaries = BTreeSet::new();
+    for path in dir_entries(&dirs.index_dir)? {
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => match CacheEntry::load(&dirs.index_dir, &stem) {
+                Some(entry) => {
+                    let item = crate_item(&mut crates, dirs, &entry.crate_name);
+                    item.paths.push(path);
+                    item.paths.push(dirs.bin_dir.join(&stem));
+                    item.binaries.push(dirs.bin_dir.join(&stem));
+                    if entry.last_used >= item.last_used {
+                        item.source_path = Some(entry.source_path);
+                        item.content_hash = Some(entry.content_hash);
+                        item.last_used = entry.last_used;
+                    }
+                    binaries.insert(stem);
+                }
+                None => crate_item(&mut crates, dirs, &stem).paths.push(path),
+            },
+            Some("lock") => {
+                crate_item(&mut crates, dirs, &stem);
+            }
+            _ => {}
+        }
+    }
+
+    for path in dir_entries(&dirs.src_dir)? {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        crate_item(&mut crates, dirs, &name).paths.push(path);
+    }
+
+    for path in dir_entries(&dirs.bin_dir)? {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if !binaries.contains(&name) {
+            let item = crate_item(&mut crates, dirs, &name);
+            item.paths.push(path.clone());
+            item.binaries.push(path);
+        }
+    }
+
+    let mut items: Vec<CacheItem> = crates.into_values().collect();
+
+    for path in dir_entries(&dirs.tmp_dir)? {
+        if path.is_dir() {
+            let dir = path.file_name().unwrap().to_string_lossy().into_owned();
+            items.push(CacheItem {
+                name: format!("target/{dir}"),
+                source_path: None,
+                content_hash: None,
+                binaries: Vec::new(),
+                lock_path: path.join(".cargo-lock"),
+                paths: vec![path, TargetEntry::path(&dirs.index_dir, &dir)],
+                last_used: TargetEntry::load(&dirs.index_dir, &dir)
+                    .map(|entry| entry.last_used)
+                    .unwrap_or(0),
+                size: 0,
+            });
+        }
+    }
+
+    // Entries from before we recorded when they were used fall back to the
+    // modification times of their files.
+    for item in items.iter_mut() {
+        item.paths.retain(|path| path.symlink_metadata().is_ok());
+        item.binaries.retain(|path| path.symlink_metadata().is_ok());
+        for path in item.paths.iter().chain([&item.lock_path]) {
+            item.size += disk_size(path);
+            if let Some(modified) = fs::symlink_metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            {
+                item.last_used = item.last_used.max(modified.as_secs());
+            }
+        }
+    }
+
+    items.sort_by_key(|item| item.last_used);
+    Ok(items)
+}
+
+/// Removes the cache items that haven't been used within the configured
+/// maximum age, then the least recently used items until the cache fits in
+/// its size budget. Items that are locked because they're in use are skipped.
+pub fn collect_garbage(dirs: &DataDirs, config: &CacheConfig) -> Result<()> {
+    let gc_lock = open_lock(&gc_lock_path(dirs))?;
+    match gc_lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            debug!("garbage collection is already running");
+            return Ok(());
+        }
+        Err(TryLockError::Error(err)) => return Err(err.into()),
+    }
+    gc_lock.set_modified(SystemTime::now())?;
+
+    let now = now_seconds();
+    let max_age = config.max_age_days * 24 * 60 * 60;
+    let max_size = config.max_size_mb * 1024 * 1024;
+
+    let items = cache_items(dirs)?;
+    let mut total_size: u64 = items.iter().map(|item| item.size).sum();
+    for item in items {
+        let expired = now.saturating_sub(item.last_used) > max_age;
+        if !expired && total_size <= max_size {
+            break;
+        }
+
+        match remove_item(&item) {
+            Ok(true) => {
+                info!("removed {} ({} bytes)", item.name, item.size);
+                total_size -= item.size;
+            }
+            Ok(false) => debug!("skipping {}, which is in use", item.name),
+            Err(err) => warn!("failed to remove {}: {err}", item.name),
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts garbage collection in a detached process, so that it doesn't delay
+/// anything, unless it's already run within the last hour.
+pub fn spawn_background_gc(dirs: &DataDirs) {
+    let path = gc_lock_path(dirs);
+    let due = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|elapsed| elapsed.as_secs() >= GC_INTERVAL_SECONDS)
+        .unwrap_or(true);
+    if !due {
+        return;
+    }
+
+    // Updating the time first keeps concurrent runs from all starting one.
+    let spawned = open_lock(&path)
+        .and_then(|file| Ok(file.set_modified(SystemTime::now())?))
+        .and_then(|()| Ok(std::env::current_exe()?))
+        .and_then(|exe| {
+            Ok(Command::new(exe)
+                .args(["cache", "gc"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?)
+        });
+    if let Err(err) = spawned {
+        debug!("unable to start garbage collection: {err}");
+    }
+}
+
+/// An item in the cache, as printed by `rust cache list --json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedItem {
+    /// The crate name, or `target/{dir}` for a build directory.
+    pub name: String,
+    /// The path of the script that the crate was generated for, if known.
+    pub source_path: Option<PathBuf>,
+    /// The git hash of the script's source files when it was last built.
+    pub content_hash: Option<String>,
+    /// The script's compiled binaries.
+    pub binaries: Vec<PathBuf>,
+    /// When the item was last used, in seconds since the unix epoch.
+    pub last_used: u64,
+    /// The total size of the item's files, in bytes.
+    pub size: u64,
+}
+
+/// The locations of a script's cached crate, as printed by `rust cache path`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptPaths {
+    pub crate_name: String,
+    /// The generated crate that cargo builds.
+    pub crate_dir: PathBuf,
+    /// The directory in the shared cargo target directory that the script's
+    /// most recent binary was built in, or would be built in by default.
+    pub target_dir: PathBuf,
+    pub binaries: Vec<PathBuf>,
+}
+
+/// Prints everything in the cache, most recently used first.
+pub fn list_cache(json: bool) -> Result<()> {
+    let dirs = DataDirs::new()?;
+    let mut items = cache_items(&dirs)?;
+    items.reverse();
+
+    if json {
+        let items: Vec<ListedItem> = items
+            .into_iter()
+            .map(|item| ListedItem {
+                name: item.name,
+                source_path: item.source_path,
+                content_hash: item.content_hash,
+                binaries: item.binaries,
+                last_used: item.last_used,
+                size: item.size,
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&items)?);
+        return Ok(());
+    }
+
+    let now = now_seconds();
+    let width = items.iter().map(|item| item.name.len()).max().unwrap_or(0);
+    for item in items.iter() {
+        let hash = item.content_hash.as_deref().unwrap_or("-");
+        println!(
+            "{:width$}  {:>9}  {:>14}  {:8}  {}",
+            item.name,
+            format_size(item.size),
+            format_age(now, item.last_used),
+            hash.get(..8).unwrap_or(hash),
+            item.source_path
+                .as_deref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints where a script's crate and binaries are cached.
+pub fn print_cache_paths(path: &Path, json: bool) -> Result<()> {
+    let dirs = DataDirs::new()?;
+    let mut paths: Vec<ScriptPaths> = script_items(cache_items(&dirs)?, path)
+        .into_iter()
+        .map(|item| ScriptPaths {
+            crate_dir: dirs.src_dir.join(&item.name),
+            target_dir: target_dir(&dirs, &item.name),
+            crate_name: item.name,
+            binaries: item.binaries,
+        })
+        .collect();
+    if paths.is_empty() {
+        let crate_name = script_crate_name(path);
+        paths.push(ScriptPaths {
+            crate_dir: dirs.src_dir.join(&crate_name),
+            target_dir: target_dir(&dirs, &crate_name),
+            crate_name,
+            binaries: Vec::new(),
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&paths)?);
+        return Ok(());
+    }
+
+    for paths in paths.iter() {
+        println!("crate-dir: {}", paths.crate_dir.display());
+        println!("target-dir: {}", paths.target_dir.display());
+        for binary in paths.binaries.iter() {
+            println!("binary: {}", binary.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the profile directory that a crate's most recently used binary was
+/// built in, or the one for the default `dev` profile if it hasn't been built.
+fn target_dir(dirs: &DataDirs, crate_name: &str) -> PathBuf {
+    let profile = CacheEntry::load_crate(&dirs.index_dir, crate_name)
+        .into_iter()
+        .max_by_key(|entry| entry.last_used)
+        .map(|entry| entry.profile)
+        .unwrap_or_else(|| "dev".to_string());
+    dirs.tmp_dir.join(profile_dir(&profile))
+}
+
+/// Removes a script's cached crate and binaries, or everything in the cache
+/// if no script is given. Anything that's in use is left alone.
+pub fn clear_cache(path: Option<&Path>, json: bool) -> Result<()> {
+    let dirs = DataDirs::new()?;
+    let items = cache_items(&dirs)?;
+    let items = match path {
+        Some(path) => script_items(items, path),
+        None => items,
+    };
+
+    let mut removed = Vec::new();
+    let mut in_use = Vec::new();
+    for item in items {
+        // Our logs go to stdout, where they'd get mixed up with the JSON.
+        if remove_item(&item)? {
+            if !json {
+                info!("removed {} ({})", item.name, format_size(item.size));
+            }
+            removed.push(item.name);
+        } else {
+            if !json {
+                warn!("{} is in use, so it wasn't removed", item.name);
+            }
+            in_use.push(item.name);
+        }
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "removed": removed,
+                "in_use": in_use,
+            }))?
+        );
+    }
+
+    Ok(())
+}
+
+/// Finds the cached crates for a script: the one it has when it's given as
+/// `path`, and any others that were last built from the same file by a
+/// different absolute path.
+fn script_items(items: Vec<CacheItem>, path: &Path) -> Vec<CacheItem> {
+    let crate_name = script_crate_name(path);
+    let canonical = fs::canonicalize(path).ok();
+    items
+        .into_iter()
+        .filter(|item| {
+            item.name == crate_name
+                || (canonical.is_some()
+                    && item
+                        .source_path
+                        .as_deref()
+                        .filter(|source_path| source_path.is_absolute())
+                        .and_then(|source_path| fs::canonicalize(source_path).ok())
+                        == canonical)
+        })
+        .collect()
+}
+
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 || unit == "GiB" {
+            return if unit == "B" {
+                format!("{bytes} {unit}")
+            } else {
+                format!("{size:.1} {unit}")
+            };
+        }
+        size /= 1024.0;
+    }
+    unreachable!()
+}
+
+fn format_age(now: u64, then: u64) -> String {
+    let seconds = now.saturating_sub(then);
+    let (count, unit) = match seconds {
+        _ if then == 0 => return "never".to_string(),
+        0..=59 => return "just now".to_string(),
+        60..=3_599 => (seconds / 60, "minute"),
+        3_600..=86_399 => (seconds / 3_600, "hour"),
+        _ => (seconds / 86_400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
+
+fn crate_item<'a>(
+    crates: &'a mut BTreeMap<String, CacheItem>,
+    dirs: &DataDirs,
+    name: &str,
+) -> &'a mut CacheItem {
+    crates.entry(name.to_string()).or_insert_with(|| CacheItem {
+        name: name.to_string(),
+        source_path: None,
+        content_hash: None,
+        paths: Vec::new(),
+        binaries: Vec::new(),
+        lock_path: lock_path(&dirs.index_dir, name),
+        last_used: 0,
+        size: 0,
+    })
+}
+
+fn gc_lock_path(dirs: &DataDirs) -> PathBuf {
+    dirs.data_dir.join("gc.lock")
+}
+
+fn open_lock(path: &Path) -> Result<File> {
+    File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("failed to open {}", path.display()))
+}
+
+/// Removes an item's files, returning `false` instead if it's in use.
+pub fn remove_item(item: &CacheItem) -> Result<bool> {
+    let lock = match open_lock(&item.lock_path) {
+        Ok(lock) => lock,
+        // The build directory that would contain the lock is already gone.
+        Err(_) if !item.paths.iter().any(|path| path.exists()) => return Ok(true),
+        Err(err) => return Err(err),
+    };
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(false),
+        Err(TryLockError::Error(err)) => return Err(err.into()),
+    }
+
+    for path in item.paths.iter().chain([&item.lock_path]) {
+        let removed = if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        match removed {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err).wrap_err_with(|| format!("failed to remove {}", path.display()))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(true)
+}
+
+fn dir_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)
+        .wrap_err_with(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns the total size of a file or directory, in bytes.
+fn disk_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| disk_size(&entry.path()))
+            .sum(),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+#[test]
+fn test_collect_garbage() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("rust-exe-test-gc-{}", std::process::id()));
+    let dirs = DataDirs {
+        data_dir: root.clone(),
+        src_dir: root.join("src"),
+        bin_dir: root.join("bin"),
+        index_dir: root.join("index"),
+        tmp_dir: root.join("tmp"),
+    };
+    for dir in [&dirs.src_dir, &dirs.bin_dir, &dirs.index_dir, &dirs.tmp_dir] {
+        fs::create_dir_all(dir.join("nested"))?;
+    }
+    fs::remove_dir(dirs.index_dir.join("nested"))?;
+    fs::remove_dir(dirs.bin_dir.join("nested"))?;
+
+    for (crate_name, profile) in [("used", "dev"), ("used", "release"), ("unused", "dev")] {
+        CacheEntry {
+            crate_name: crate_name.to_string(),
+            profile: profile.to_string(),
+            source_path: format!("{crate_name}.rs").into(),
+            content_hash: String::new(),
+            build_hash: String::new(),
+            last_used: 0,
+            script_profile: "dev".to_string(),
+            modules: Vec::new(),
+            input_hash: String::new(),
+        }
+        .save(&dirs.index_dir)?;
+        fs::write(
+            dirs.bin_dir.join(binary_name(crate_name, profile)),
+            "binary",
+        )?;
+    }
+    fs::write(dirs.bin_dir.join("legacy"), "binary")?;
+    TargetEntry::touch(&dirs.index_dir, "nested")?;
+
+    let items = cache_items(&dirs)?;
+    let target = items.iter().find(|item| item.name == "target/nested");
+    assert!(target.unwrap().last_used + 60 > now_seconds());
+    let mut names: Vec<_> = items
+        .iter()
+        .map(|item| (item.name.as_str(), item.paths.len(), item.size))
+        .collect();
+    names.sort();
+    expect![[r#"
+        [
+            (
+                "legacy",
+                1,
+                6,
+            ),
+            (
+                "nested",
+                1,
+                0,
+            ),
+            (
+                "target/nested",
+                2,
+                23,
+            ),
+            (
+                "unused",
+                2,
+                170,
+            ),
+            (
+                "used",
+                4,
+                336,
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(&names);
+
+    let lock = lock_crate(&dirs.index_dir, "used")?;
+    let config = CacheConfig {
+        max_size_mb: 0,
+        ..CacheConfig::default()
+    };
+    collect_garbage(&dirs, &config)?;
+
+    let remaining: Vec<_> = cache_items(&dirs)?
+        .into_iter()
+        .map(|item| item.name)
+        .collect();
+    assert_eq!(remaining, ["used"]);
+    assert!(dirs.bin_dir.join("used-release").exists());
+    assert!(!TargetEntry::path(&dirs.index_dir, "nested").exists());
+
+    drop(lock);
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_format_size_and_age() {
+    assert_eq!(format_size(474), "474 B");
+    assert_eq!(format_size(6_270_402), "6.0 MiB");
+    assert_eq!(format_size(76_619_264), "73.1 MiB");
+    assert_eq!(format_size(5 << 40), "5120.0 GiB");
+
+    assert_eq!(format_age(1_000, 0), "never");
+    assert_eq!(format_age(1_000, 990), "just now");
+    assert_eq!(format_age(1_000, 900), "1 minute ago");
+    assert_eq!(format_age(100_000, 90_000), "2 hours ago");
+    assert_eq!(format_age(1_000_000, 100_000), "10 days ago");
+}
@@ -13,15 +13,55 @@ static GIT_USER_NAME: &str = "rust-exe[bot]";
 static GIT_USER_EMAIL: &str = "107450506+rust-exe[bot]@users.noreply.github.com";
 
 pub fn git_blob_sha1_hex(content: &[u8]) -> String {
+    hex(&git_object_sha1(b"blob", content))
+}
+
+/// Hashes a list of files as a git tree object containing their blobs. Nested
+/// paths are used as entry names directly, rather than as nested trees.
+pub fn git_tree_sha1_hex<'a>(files: impl IntoIterator<Item = (String, &'a [u8])>) -> String {
+    let mut entries: Vec<(String, [u8; 20])> = files
+        .into_iter()
+        .map(|(name, content)| (name, git_object_sha1(b"blob", content)))
+        .collect();
+    entries.sort();
+
+    let mut tree = Vec::new();
+    for (name, hash) in entries {
+        tree.extend(b"100644 ");
+        tree.extend(name.as_bytes());
+        tree.push(b'\0');
+        tree.extend(hash);
+    }
+    hex(&git_object_sha1(b"tree", &tree))
+}
+
+fn git_object_sha1(kind: &[u8], content: &[u8]) -> [u8; 20] {
     let mut hasher = Sha1::default();
-    hasher.update(b"blob ");
+    hasher.update(kind);
+    hasher.update(b" ");
     hasher.update(content.len().to_string());
     hasher.update(b"\0");
     hasher.update(content);
-    let result = hasher.finalize();
-    let mut hex = String::with_capacity(40);
-    for byte in result {
+    hasher.finalize().into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
         write!(hex, "{:02x}", byte).unwrap();
     }
     hex
 }
+
+#[test]
+fn test_git_hashing() {
+    // as per `git hash-object` and `git mktree`
+    assert_eq!(
+        git_blob_sha1_hex(b"hello, rust\n"),
+        "ef75ac64003ea976df40b39fee718bdf1c7282e0"
+    );
+    assert_eq!(
+        git_tree_sha1_hex([("hello.rs".to_string(), &b"hello, rust\n"[..])]),
+        "03f6012cbbb73a7810f6a40f59b9a15c8f2cf1f5"
+    );
+}
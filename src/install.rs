@@ -0,0 +1,180 @@
+use {
+    crate::*,
+    ::eyre::WrapErr,
+    ::serde::{Deserialize, Serialize},
+    ::std::{collections::BTreeMap, fs, io::ErrorKind, path::Path},
+};
+
+/// The binaries that `rust install` has put on the `PATH`, recorded in
+/// `~/.rust-exe/installed.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Installed {
+    /// Installed binaries, by name.
+    pub binaries: BTreeMap<String, InstalledBinary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InstalledBinary {
+    pub binary_path: PathBuf,
+    pub source_path: PathBuf,
+    /// The git hash of the script's source files when it was installed.
+    pub content_hash: String,
+}
+
+impl Installed {
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("installed.toml")
+    }
+
+    /// Loads the record of installed binaries, which is empty if nothing has
+    /// been installed yet.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = Self::path(data_dir);
+        match fs::read_to_string(&path) {
+            Ok(toml) => toml_edit::easy::from_str(&toml)
+                .map_err(|err| eyre::eyre!("invalid install record {}: {err}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Installed::default()),
+            Err(err) => Err(err).wrap_err_with(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        fs::write(
+            Self::path(data_dir),
+            toml_edit::easy::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the directory that binaries are installed to: the configured
+/// `install-dir`, or `~/.local/bin`.
+pub fn install_dir(config: &Config) -> Result<PathBuf> {
+    match &config.install_dir {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(::home::home_dir()
+            .ok_or_else(|| eyre::eyre!("unable to determine home directory"))?
+            .join(".local")
+            .join("bin")),
+    }
+}
+
+/// Builds a script in release mode, or with the profile its metadata chooses,
+/// and installs its binary as `name`, which defaults to the script's name in
+/// kebab case.
+pub fn install_script(path: PathBuf, body: String, name: Option<String>) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => path.file_stem().unwrap().to_string_lossy().to_kebab_case(),
+    };
+    if name.is_empty() || name.contains(std::path::is_separator) || name.starts_with('.') {
+        eyre::bail!("invalid binary name {name:?}");
+    }
+
+    let mut script = ScriptCrate::generate(path, &body)?;
+    if script.profile == "dev" {
+        script.profile = "release".to_string();
+    }
+    let built = script.build()?;
+
+    let config = Config::load(&script.dirs.data_dir)?;
+    let dir = install_dir(&config)?;
+    fs::create_dir_all(&dir)?;
+
+    // Copying next to the destination and renaming replaces the binary even if
+    // an old version of it is running.
+    let binary_path = dir.join(&name);
+    let partial_path = dir.join(format!(".{name}.partial"));
+    fs::copy(&built, &partial_path)
+        .and_then(|_| fs::rename(&partial_path, &binary_path))
+        .wrap_err_with(|| format!("failed to install {}", binary_path.display()))?;
+
+    let mut installed = Installed::load(&script.dirs.data_dir)?;
+    installed.binaries.insert(
+        name,
+        InstalledBinary {
+            binary_path: binary_path.clone(),
+            source_path: fs::canonicalize(&script.path)?,
+            content_hash: script.content_hash.clone(),
+        },
+    );
+    installed.save(&script.dirs.data_dir)?;
+
+    info!("installed {}", binary_path.display());
+    spawn_background_gc(&script.dirs);
+    Ok(())
+}
+
+/// Removes a binary that was installed by `rust install`.
+pub fn uninstall_binary(name: &str) -> Result<()> {
+    let dirs = DataDirs::new()?;
+    let mut installed = Installed::load(&dirs.data_dir)?;
+    let binary = installed
+        .binaries
+        .remove(name)
+        .ok_or_else(|| eyre::eyre!("{name} isn't installed"))?;
+
+    match fs::remove_file(&binary.binary_path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(err)
+                .wrap_err_with(|| format!("failed to remove {}", binary.binary_path.display()))
+        }
+        _ => {}
+    }
+    installed.save(&dirs.data_dir)?;
+
+    info!("uninstalled {}", binary.binary_path.display());
+    Ok(())
+}
+
+/// Prints each installed binary with the script and content hash it was
+/// built from.
+pub fn list_installed() -> Result<()> {
+    let dirs = DataDirs::new()?;
+    let installed = Installed::load(&dirs.data_dir)?;
+
+    for (name, binary) in installed.binaries.iter() {
+        println!(
+            "{name}\t{}\t{}",
+            binary.content_hash.get(..8).unwrap_or(&binary.content_hash),
+            binary.source_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_installed() {
+    let mut installed = Installed::default();
+    installed.binaries.insert(
+        "hello".to_string(),
+        InstalledBinary {
+            binary_path: "/home/user/.local/bin/hello".into(),
+            source_path: "/home/user/scripts/hello.rs".into(),
+            content_hash: "0123456789abcdef0123456789abcdef01234567".to_string(),
+        },
+    );
+
+    let toml = toml_edit::easy::to_string_pretty(&installed).unwrap();
+    expect![[r#"
+
+        [binaries.hello]
+        binary-path = "/home/user/.local/bin/hello"
+        source-path = "/home/user/scripts/hello.rs"
+        content-hash = "0123456789abcdef0123456789abcdef01234567"
+    "#]]
+    .assert_eq(&toml);
+
+    let loaded: Installed = toml_edit::easy::from_str(&toml).unwrap();
+    assert_eq!(
+        loaded.binaries["hello"].source_path,
+        Path::new("/home/user/scripts/hello.rs")
+    );
+    assert!(toml_edit::easy::from_str::<Installed>("")
+        .unwrap()
+        .binaries
+        .is_empty());
+}
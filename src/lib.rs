@@ -12,9 +12,12 @@ pub(crate) use expect_test::expect;
 #[doc(hidden)]
 #[allow(unused)]
 pub(crate) use {
-    crate::{arg_stream::*, cli::*, crates::*, git_hashing::*, run::*, toolchain::*, util::*},
+    crate::{
+        arg_stream::*, cache::*, cli::*, config::*, crates::*, diagnostics::*, features::*, gc::*,
+        git_hashing::*, install::*, lockfile::*, metadata::*, modules::*, registry::*, run::*,
+        toolchain::*, util::*,
+    },
     ::{
-        cargo_lock::Lockfile,
         eyre::Result,
         heck::*,
         indexmap::{IndexMap, IndexSet},
@@ -36,12 +39,32 @@ pub(crate) use {
 #[doc(hidden)]
 pub(crate) mod arg_stream;
 #[doc(hidden)]
+pub(crate) mod cache;
+#[doc(hidden)]
 pub(crate) mod cli;
 #[doc(hidden)]
+pub(crate) mod config;
+#[doc(hidden)]
 pub(crate) mod crates;
 #[doc(hidden)]
+pub(crate) mod diagnostics;
+#[doc(hidden)]
+pub(crate) mod features;
+#[doc(hidden)]
+pub(crate) mod gc;
+#[doc(hidden)]
 pub(crate) mod git_hashing;
 #[doc(hidden)]
+pub(crate) mod install;
+#[doc(hidden)]
+pub(crate) mod lockfile;
+#[doc(hidden)]
+pub(crate) mod metadata;
+#[doc(hidden)]
+pub(crate) mod modules;
+#[doc(hidden)]
+pub(crate) mod registry;
+#[doc(hidden)]
 pub(crate) mod run;
 #[doc(hidden)]
 pub(crate) mod toolchain;
@@ -0,0 +1,207 @@
+use {
+    crate::*,
+    ::serde::Deserialize,
+    ::std::{collections::BTreeSet, path::Path},
+};
+
+/// A package from a `Cargo.lock` file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: semver::Version,
+    pub source: Option<String>,
+    /// The package's dependencies, as `name` or `name version`.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+/// Reads the packages from a `Cargo.lock` file, of any format version.
+pub fn read_lockfile(path: &Path) -> Result<Vec<LockedPackage>> {
+    #[derive(Deserialize)]
+    struct Lockfile {
+        #[serde(default)]
+        package: Vec<LockedPackage>,
+    }
+
+    let toml = std::fs::read_to_string(path)?;
+    let lockfile: Lockfile = toml_edit::easy::from_str(&toml)
+        .map_err(|err| eyre::eyre!("invalid lockfile {}: {err}", path.display()))?;
+    Ok(lockfile.package)
+}
+
+/// The most `cargo update --precise` commands that [`time_travel_lockfile`] will
+/// run for one crate, since each of them can take a while.
+const MAX_PRECISE_UPDATES: usize = 32;
+
+/// Generates the lockfile for a crate, then downgrades any registry packages
+/// in it that were published after `timestamp` to the newest compatible
+/// versions that had been published by then, where their dependents allow it.
+pub fn time_travel_lockfile(
+    crate_path: &Path,
+    toolchain: &Toolchain,
+    registry: &Registry,
+    timestamp: u64,
+) -> Result<()> {
+    let cargo = |args: &[&str]| -> Result<bool> {
+        let output = toolchain
+            .cargo()
+            .args(args)
+            .current_dir(crate_path)
+            .output()?;
+        if !output.status.success() {
+            debug!(
+                "cargo {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.status.success())
+    };
+
+    if !cargo(&["generate-lockfile", "--quiet"])? {
+        eyre::bail!("failed to generate lockfile");
+    }
+
+    // Downgrading a package can change which dependencies it pulls in, so we
+    // repeat until nothing changes, within reason. Each package version is
+    // only tried once, since `cargo update` resolves the whole graph each time.
+    let mut attempted = BTreeSet::new();
+    let mut undated = BTreeSet::new();
+    for _ in 0..4 {
+        let mut changed = false;
+
+        for package in read_lockfile(&crate_path.join("Cargo.lock"))? {
+            let from_registry = package
+                .source
+                .as_deref()
+                .map(|source| source.starts_with("registry+"))
+                .unwrap_or(false);
+            if !from_registry {
+                continue;
+            }
+
+            let versions = registry.versions(&package.name);
+            let locked = match versions
+                .iter()
+                .find(|version| version.vers == package.version)
+            {
+                Some(locked) => locked,
+                None => continue,
+            };
+            match published_by(locked, &versions) {
+                Some(published) if published <= timestamp => continue,
+                Some(_) => {}
+                None => {
+                    undated.insert(package.name.clone());
+                    continue;
+                }
+            }
+
+            let compatible = compatible_up_to(&package.version);
+            let target = match latest_published_before(&versions, &compatible, timestamp) {
+                Some(target) => target.vers.to_string(),
+                None => {
+                    if has_undated_versions(&versions, &compatible) {
+                        undated.insert(package.name.clone());
+                    }
+                    continue;
+                }
+            };
+
+            let spec = format!("{}@{}", package.name, package.version);
+            if !attempted.insert(spec.clone()) {
+                continue;
+            }
+            if attempted.len() > MAX_PRECISE_UPDATES {
+                debug!("giving up on locking dependencies after {MAX_PRECISE_UPDATES} updates");
+                return Ok(());
+            }
+            if cargo(&[
+                "update",
+                "--quiet",
+                "--package",
+                &spec,
+                "--precise",
+                &target,
+            ])? {
+                debug!("downgraded {spec} to {target} as of {timestamp}");
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    if !undated.is_empty() {
+        warn!(
+            "The registry index doesn't say which versions of {} had been published \
+             when the script was written, so they may be newer than the script.",
+            undated.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_read_lockfile() {
+    let path = std::env::temp_dir().join(format!("rust-exe-test-{}.lock", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+        version = 4
+
+        [[package]]
+        name = "hello"
+        version = "0.0.0-ef75ac64"
+        dependencies = [
+         "serde",
+         "syn 1.0.109",
+        ]
+
+        [[package]]
+        name = "serde"
+        version = "1.0.137"
+        source = "registry+https://github.com/rust-lang/crates.io-index"
+        checksum = "61ea8d54c77f8315140a05f4c7237403bf38b72704d031543aa1d16abbf517d1"
+        "#,
+    )
+    .unwrap();
+
+    let packages = read_lockfile(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    expect![[r#"
+        [
+            LockedPackage {
+                name: "hello",
+                version: Version {
+                    major: 0,
+                    minor: 0,
+                    patch: 0,
+                    pre: Prerelease("ef75ac64"),
+                },
+                source: None,
+                dependencies: [
+                    "serde",
+                    "syn 1.0.109",
+                ],
+            },
+            LockedPackage {
+                name: "serde",
+                version: Version {
+                    major: 1,
+                    minor: 0,
+                    patch: 137,
+                },
+                source: Some(
+                    "registry+https://github.com/rust-lang/crates.io-index",
+                ),
+                dependencies: [],
+            },
+        ]
+    "#]]
+    .assert_debug_eq(&packages.unwrap());
+}
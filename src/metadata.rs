@@ -0,0 +1,353 @@
+use crate::*;
+
+/// Returns the text of a file's crate-level doc comments (`//!` and `#![doc]`),
+/// one line per attribute.
+pub fn crate_doc(file: &syn::File) -> String {
+    let mut crate_doc = String::new();
+
+    for attr in file.attrs.iter() {
+        if attr.path.is_ident("doc") {
+            if let Ok(syn::Meta::NameValue(meta)) = attr.parse_meta() {
+                if let syn::Lit::Str(lit) = meta.lit {
+                    crate_doc.push_str(&lit.value());
+                    crate_doc.push('\n');
+                }
+            }
+        }
+    }
+
+    crate_doc
+}
+
+/// Returns the names of the unstable features enabled by a file's crate-level
+/// `#![feature(...)]` attributes.
+pub fn feature_gates(file: &syn::File) -> Vec<String> {
+    file.attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("feature"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|nested| match nested {
+            syn::NestedMeta::Meta(meta) => meta.path().get_ident().map(|ident| ident.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the contents of the first ```` ```cargo ```` fenced block in a doc
+/// string, if there is one.
+pub fn cargo_block(doc: &str) -> Option<String> {
+    let mut lines = doc.lines();
+
+    lines.find(|line| is_cargo_fence(line))?;
+
+    let mut block = String::new();
+    for line in lines {
+        if line.trim() == "```" {
+            return Some(block);
+        }
+        block.push_str(line.strip_prefix(' ').unwrap_or(line));
+        block.push('\n');
+    }
+
+    warn!("ignoring unterminated ```cargo block in crate docs");
+    None
+}
+
+fn is_cargo_fence(line: &str) -> bool {
+    line.trim()
+        .strip_prefix("```")
+        .map(|info| info.split(',').next().unwrap_or_default().trim() == "cargo")
+        .unwrap_or(false)
+}
+
+/// Removes the first ```` ```cargo ```` block from a source file's `//!` crate
+/// docs, along with the empty doc lines that separated it from the rest of them.
+pub fn strip_cargo_block(source: &str) -> String {
+    let lines: Vec<&str> = source.split_inclusive('\n').collect();
+    let doc_text = |line: &str| line.trim_start().strip_prefix("//!").map(str::to_string);
+    let is_empty_doc = |line: &str| doc_text(line).is_some_and(|text| text.trim().is_empty());
+
+    let start = match lines
+        .iter()
+        .position(|line| doc_text(line).is_some_and(|text| is_cargo_fence(&text)))
+    {
+        Some(start) => start,
+        None => return source.to_string(),
+    };
+    let end = match lines[start + 1..]
+        .iter()
+        .position(|line| doc_text(line).is_some_and(|text| text.trim() == "```"))
+    {
+        Some(offset) => start + 1 + offset,
+        None => return source.to_string(),
+    };
+
+    let mut before = &lines[..start];
+    let mut after = &lines[end + 1..];
+    while after.first().is_some_and(|line| is_empty_doc(line)) {
+        after = &after[1..];
+    }
+    if after.first().is_none_or(|line| doc_text(line).is_none()) {
+        while before.last().is_some_and(|line| is_empty_doc(line)) {
+            before = &before[..before.len() - 1];
+        }
+    }
+
+    [before, after].concat().concat()
+}
+
+/// Parses the manifest from a ```` ```cargo ```` block in the crate docs, if
+/// there is one.
+pub fn doc_manifest(file: &syn::File) -> Result<Option<Toml>> {
+    cargo_block(&crate_doc(file))
+        .map(|block| {
+            toml_edit::easy::from_str(&block)
+                .map_err(|err| eyre::eyre!("invalid ```cargo block in crate docs: {err}"))
+        })
+        .transpose()
+}
+
+/// Removes a `cargo -Zscript`-style frontmatter block (a `---`-fenced manifest
+/// following the optional shebang line) from a script body. The block's lines
+/// are replaced with empty lines, so line numbers in the remaining source are
+/// unchanged. Returns the remaining source and the parsed manifest, if any.
+pub fn strip_frontmatter(body: &str) -> Result<(String, Option<Toml>)> {
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+
+    let (start, end) = match find_frontmatter(&lines)? {
+        Some(fences) => fences,
+        None => return Ok((body.to_string(), None)),
+    };
+
+    let content: String = lines[start + 1..end].concat();
+    let manifest = toml_edit::easy::from_str(&content)
+        .map_err(|err| eyre::eyre!("invalid frontmatter manifest: {err}"))?;
+
+    let mut stripped = String::with_capacity(body.len());
+    for (index, line) in lines.iter().enumerate() {
+        if (start..=end).contains(&index) {
+            if line.ends_with('\n') {
+                stripped.push('\n');
+            }
+        } else {
+            stripped.push_str(line);
+        }
+    }
+
+    Ok((stripped, Some(manifest)))
+}
+
+/// Splits a script body into its header, consisting of the shebang line and
+/// frontmatter block (if either is present), and the Rust source that follows.
+pub fn split_header(body: &str) -> Result<(&str, &str)> {
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+
+    let header_lines = match find_frontmatter(&lines)? {
+        Some((_, end)) => end + 1,
+        None if is_shebang(lines.first().copied().unwrap_or_default()) => 1,
+        None => 0,
+    };
+    let header_len = lines[..header_lines].iter().map(|line| line.len()).sum();
+
+    Ok(body.split_at(header_len))
+}
+
+fn is_shebang(line: &str) -> bool {
+    line.starts_with("#!") && !line.starts_with("#![")
+}
+
+/// Finds the frontmatter block in a script's lines, returning the indices of
+/// its opening and closing fences.
+fn find_frontmatter(lines: &[&str]) -> Result<Option<(usize, usize)>> {
+    let mut index = 0;
+    if lines.first().map(|line| is_shebang(line)).unwrap_or(false) {
+        index += 1;
+    }
+    while index < lines.len() && lines[index].trim().is_empty() {
+        index += 1;
+    }
+
+    let opening = match lines.get(index) {
+        Some(line) if line.starts_with("---") => line.trim_end(),
+        _ => return Ok(None),
+    };
+    let dashes = opening.len() - opening.trim_start_matches('-').len();
+    let fence = &opening[..dashes];
+    let info = opening[dashes..].trim();
+    if !info.is_empty() && info != "cargo" {
+        eyre::bail!("unsupported frontmatter type {info:?}, expected `cargo`");
+    }
+
+    let start = index;
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_end() == fence)
+        .map(|offset| start + 1 + offset)
+        .ok_or_else(|| eyre::eyre!("unterminated frontmatter, expected a closing {fence:?}"))?;
+
+    Ok(Some((start, end)))
+}
+
+/// Merges `overrides` into `base`, recursing into tables that are present in
+/// both. Any other value from `overrides` replaces the one in `base`.
+pub fn merge_toml(base: &mut Toml, overrides: Toml) {
+    match (base, overrides) {
+        (Toml::Table(base), Toml::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[test]
+fn test_doc_manifest() {
+    let file = syn::parse_file(
+        r#"
+        //! Prints a greeting.
+        //!
+        //! ```cargo
+        //! [dependencies]
+        //! serde = { version = "1.0.137", default-features = false }
+        //! eyre = "0.6"
+        //!
+        //! [profile.dev]
+        //! opt-level = 1
+        //! ```
+
+        fn main() {}
+        "#,
+    )
+    .unwrap();
+
+    let mut manifest = toml! {
+        [package]
+        name = "hello"
+
+        [dependencies]
+        eyre = { version = "*" }
+        serde = { version = "*" }
+        once_cell = { version = "*" }
+    };
+
+    merge_toml(&mut manifest, doc_manifest(&file).unwrap().unwrap());
+
+    expect![[r#"
+        [package]
+        name = "hello"
+
+        [dependencies]
+        eyre = "0.6"
+
+        [dependencies.serde]
+        version = "1.0.137"
+        default-features = false
+
+        [dependencies.once_cell]
+        version = "*"
+
+        [profile.dev]
+        opt-level = 1
+    "#]]
+    .assert_eq(&manifest.to_string());
+}
+
+#[test]
+fn test_strip_cargo_block() {
+    let docs = "//! ```cargo\n//! [dependencies]\n//! ```\n";
+    assert_eq!(
+        strip_cargo_block(&format!("{docs}fn main() {{}}\n")),
+        "fn main() {}\n"
+    );
+    assert_eq!(
+        strip_cargo_block(&format!(
+            "//! Prints a greeting.\n//!\n{docs}//!\n//! Usage.\n"
+        )),
+        "//! Prints a greeting.\n//!\n//! Usage.\n"
+    );
+    assert_eq!(
+        strip_cargo_block(&format!(
+            "//! Prints a greeting.\n//!\n{docs}\nfn main() {{}}"
+        )),
+        "//! Prints a greeting.\n\nfn main() {}"
+    );
+    assert_eq!(
+        strip_cargo_block(&format!("{docs}//!\n//! Prints a greeting.\n")),
+        "//! Prints a greeting.\n"
+    );
+    let unterminated = "//! ```cargo\n//! [dependencies]\nfn main() {}\n";
+    assert_eq!(strip_cargo_block(unterminated), unterminated);
+}
+
+#[test]
+fn test_feature_gates() {
+    let file = syn::parse_file(
+        r#"
+        #![feature(never_type, let_chains)]
+        #![allow(unused)]
+        #![feature(try_blocks)]
+
+        fn main() {}
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        feature_gates(&file),
+        ["never_type", "let_chains", "try_blocks"]
+    );
+}
+
+#[test]
+fn test_strip_frontmatter() {
+    let (stripped, manifest) = strip_frontmatter(
+        "#!/usr/bin/env rust\n\
+         ---cargo\n\
+         [dependencies]\n\
+         eyre = \"0.6\"\n\
+         ---\n\
+         fn main() {}\n",
+    )
+    .unwrap();
+
+    assert_eq!(stripped, "#!/usr/bin/env rust\n\n\n\n\nfn main() {}\n");
+    expect![[r#"
+        [dependencies]
+        eyre = "0.6"
+    "#]]
+    .assert_eq(&manifest.unwrap().to_string());
+
+    let body = "#![allow(unused)]\n---\nfn main() {}\n";
+    let (stripped, manifest) = strip_frontmatter(body).unwrap();
+    assert_eq!(stripped, body);
+    assert!(manifest.is_none());
+
+    assert_eq!(
+        split_header("#!/usr/bin/env rust\n\n---\n[dependencies]\n---\nfn main() {}\n").unwrap(),
+        (
+            "#!/usr/bin/env rust\n\n---\n[dependencies]\n---\n",
+            "fn main() {}\n"
+        )
+    );
+    assert_eq!(
+        split_header("#!/usr/bin/env rust\nfn main() {}").unwrap(),
+        ("#!/usr/bin/env rust\n", "fn main() {}")
+    );
+    assert_eq!(
+        split_header("#![allow(unused)]\n").unwrap(),
+        ("", "#![allow(unused)]\n")
+    );
+
+    assert!(strip_frontmatter("---toml\n---\n").is_err());
+    assert!(strip_frontmatter("---\n[package]\n").is_err());
+}
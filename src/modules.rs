@@ -0,0 +1,226 @@
+use {
+    crate::*,
+    ::std::{
+        collections::VecDeque,
+        path::{Component, Path},
+    },
+};
+
+/// A module source file found by following `mod` declarations from a script.
+#[derive(Debug, Clone)]
+pub struct ModuleFile {
+    /// The path of the file, derived from the path of the script.
+    pub path: PathBuf,
+    /// The path of the file relative to the script's directory, which may begin
+    /// with `..` components if it was included with a `#[path]` attribute.
+    pub relative_path: PathBuf,
+    pub body: String,
+    pub file: SourceFile,
+}
+
+/// Finds the files of every out-of-line module (`mod foo;`) reachable from a
+/// script, following the same rules as rustc, including `#[path]` attributes.
+/// Modules whose files don't exist are skipped and left for rustc to report.
+pub fn discover_modules(script_path: &Path, file: &SourceFile) -> Result<Vec<ModuleFile>> {
+    let script_dir = script_path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut modules = Vec::new();
+    let mut queue = VecDeque::new();
+    collect_modules(
+        script_dir,
+        script_dir,
+        &file.syntax.items,
+        &mut Vec::new(),
+        &mut |found| queue.push_back(found),
+    );
+
+    while let Some((path, module_dir)) = queue.pop_front() {
+        let path = normalize(&path);
+        if modules
+            .iter()
+            .any(|module: &ModuleFile| module.path == path)
+        {
+            continue;
+        }
+
+        let body = match std::fs::read_to_string(&path) {
+            Ok(body) => body,
+            Err(err) => {
+                debug!("skipping module file {path:?}: {err}");
+                continue;
+            }
+        };
+        let file = parse_source(&body)
+            .map_err(|err| eyre::eyre!("failed to parse {}: {err}", path.display()))?;
+
+        let file_dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        collect_modules(
+            &file_dir,
+            &module_dir,
+            &file.syntax.items,
+            &mut Vec::new(),
+            &mut |found| queue.push_back(found),
+        );
+
+        modules.push(ModuleFile {
+            relative_path: relative_path(&path, script_dir),
+            path,
+            body,
+            file,
+        });
+    }
+
+    Ok(modules)
+}
+
+/// Finds the out-of-line `mod` declarations among `items`, calling `found` with
+/// the path of each module's file and the directory its own child modules live
+/// in. `file_dir` is the directory containing the current source file, and
+/// `module_dir` is the directory its child modules are resolved relative to.
+fn collect_modules(
+    file_dir: &Path,
+    module_dir: &Path,
+    items: &[syn::Item],
+    inline: &mut Vec<String>,
+    found: &mut impl FnMut((PathBuf, PathBuf)),
+) {
+    for item in items {
+        let item_mod = match item {
+            syn::Item::Mod(item_mod) => item_mod,
+            _ => continue,
+        };
+        let name = item_mod.ident.to_string();
+        let path_attr = item_mod
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("path"))
+            .find_map(|attr| match attr.parse_meta() {
+                Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })) => Some(lit.value()),
+                _ => None,
+            });
+
+        let inline_dir = inline
+            .iter()
+            .fold(module_dir.to_path_buf(), |dir, name| dir.join(name));
+
+        match (&item_mod.content, path_attr) {
+            (Some((_, items)), _) => {
+                inline.push(name);
+                collect_modules(file_dir, module_dir, items, inline, found);
+                inline.pop();
+            }
+            (None, Some(path_attr)) => {
+                // Paths are relative to the current file, unless we're in an
+                // inline module, and files loaded by path own their directory.
+                let path = if inline.is_empty() {
+                    file_dir.join(path_attr)
+                } else {
+                    inline_dir.join(path_attr)
+                };
+                let dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+                found((path, dir));
+            }
+            (None, None) => {
+                let flat = inline_dir.join(format!("{name}.rs"));
+                let nested = inline_dir.join(&name).join("mod.rs");
+                if flat.exists() || !nested.exists() {
+                    found((flat, inline_dir.join(&name)));
+                } else {
+                    found((nested, inline_dir.join(&name)));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the directory within the generated crate that a script's root file
+/// should be written to, so that each of its module files can be written at its
+/// relative path from the script without ending up outside of the crate.
+pub fn module_root_dir(script_path: &Path, modules: &[ModuleFile]) -> Result<PathBuf> {
+    let depth = modules
+        .iter()
+        .map(|module| {
+            module
+                .relative_path
+                .components()
+                .take_while(|component| *component == Component::ParentDir)
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+
+    let script_dir = normalize(&current_dir()?.join(script_path));
+    let script_dir = script_dir.parent().unwrap_or_else(|| Path::new(""));
+    let mut names: Vec<_> = script_dir
+        .components()
+        .rev()
+        .take_while(|component| matches!(component, Component::Normal(_)))
+        .take(depth)
+        .collect();
+    if names.len() < depth {
+        eyre::bail!("module files must be within the file system root");
+    }
+    names.reverse();
+
+    Ok(names.into_iter().collect())
+}
+
+/// Lexically resolves `.` and `..` components in a path, without consulting
+/// the file system.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Returns `path` relative to `base`, lexically, using `..` components where
+/// `path` isn't inside of `base`. Both should be relative to the same place.
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = normalize(path);
+    let base = normalize(base);
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+    relative
+}
+
+#[test]
+fn test_relative_path() {
+    assert_eq!(
+        relative_path(Path::new("examples/./helpers.rs"), Path::new("examples")),
+        Path::new("helpers.rs")
+    );
+    assert_eq!(
+        relative_path(Path::new("examples/../shared/x.rs"), Path::new("examples")),
+        Path::new("../shared/x.rs")
+    );
+    assert_eq!(
+        relative_path(Path::new("lib/a/b.rs"), Path::new("")),
+        Path::new("lib/a/b.rs")
+    );
+    assert_eq!(normalize(Path::new("../a/../../b")), Path::new("../../b"));
+}
@@ -0,0 +1,348 @@
+use {
+    crate::*,
+    ::serde::Deserialize,
+    ::std::{fs, path::Path},
+};
+
+/// A published version of a package, as listed in the registry index.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexVersion {
+    pub name: String,
+    pub vers: semver::Version,
+    #[serde(default)]
+    pub yanked: bool,
+    /// When the version was published, as an RFC 3339 UTC timestamp. Older
+    /// index entries may not have one.
+    #[serde(default)]
+    pub pubtime: Option<String>,
+}
+
+impl IndexVersion {
+    /// Returns when the version was published, in seconds since the unix epoch.
+    pub fn published_seconds(&self) -> Option<u64> {
+        parse_utc_timestamp(self.pubtime.as_deref()?)
+    }
+}
+
+/// The copy of the crates.io index that cargo has cached locally, which we
+/// consult without touching the network.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    cache_dirs: Vec<PathBuf>,
+}
+
+impl Registry {
+    /// Finds cargo's crates.io index caches under `$CARGO_HOME/registry/index`,
+    /// for both the sparse and git protocols.
+    pub fn open() -> Self {
+        let cargo_home = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| ::home::home_dir().map(|home| home.join(".cargo")))
+            .unwrap_or_default();
+
+        let mut cache_dirs: Vec<PathBuf> = fs::read_dir(cargo_home.join("registry").join("index"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with("index.crates.io-") || name.starts_with("github.com-")
+            })
+            .map(|entry| entry.path().join(".cache"))
+            .filter(|path| path.is_dir())
+            .collect();
+        cache_dirs.sort();
+
+        if cache_dirs.is_empty() {
+            debug!("no cached registry index found in {cargo_home:?}");
+        }
+
+        Registry { cache_dirs }
+    }
+
+    /// Returns every cached version of a package, lowest first, or an empty
+    /// list if the package isn't in the local index.
+    pub fn versions(&self, package: &str) -> Vec<IndexVersion> {
+        for cache_dir in self.cache_dirs.iter() {
+            let path = cache_dir.join(index_path(package));
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            match parse_index_cache(&bytes) {
+                Ok(mut versions) if !versions.is_empty() => {
+                    versions.sort_by(|a, b| a.vers.cmp(&b.vers));
+                    return versions;
+                }
+                Ok(_) => {}
+                Err(err) => debug!("ignoring unreadable index cache {path:?}: {err}"),
+            }
+        }
+        Vec::new()
+    }
+
+    /// Returns the name of the package that provides the crate a script refers
+    /// to as `ident`, such as `proc-macro2` for `proc_macro2`, if it's in the
+    /// local index.
+    pub fn package_name(&self, ident: &str) -> Option<String> {
+        let kebab = ident.replace('_', "-");
+        let candidates = if kebab == ident {
+            vec![ident]
+        } else {
+            vec![ident, kebab.as_str()]
+        };
+        candidates.into_iter().find_map(|candidate| {
+            let mut versions = self.versions(candidate);
+            let latest = versions.iter().rposition(|version| !version.yanked);
+            latest
+                .map(|index| versions.swap_remove(index))
+                .or_else(|| versions.pop())
+                .map(|version| version.name)
+        })
+    }
+}
+
+/// Returns the latest time that a version can have been published at. The index
+/// only records publish times for versions published since it started to, so
+/// versions without one were published before the package's earliest version
+/// with one. If none of its versions have one, we can't tell.
+pub fn published_by(version: &IndexVersion, versions: &[IndexVersion]) -> Option<u64> {
+    version.published_seconds().or_else(|| {
+        versions
+            .iter()
+            .filter_map(IndexVersion::published_seconds)
+            .min()
+    })
+}
+
+/// Returns the newest version matching `requirement` that isn't a yanked or
+/// pre-release version and was published at or before `seconds`, going by
+/// [`published_by`].
+pub fn latest_published_before<'a>(
+    versions: &'a [IndexVersion],
+    requirement: &semver::VersionReq,
+    seconds: u64,
+) -> Option<&'a IndexVersion> {
+    versions
+        .iter()
+        .filter(|version| !version.yanked && version.vers.pre.is_empty())
+        .filter(|version| requirement.matches(&version.vers))
+        .filter(|version| {
+            published_by(version, versions)
+                .map(|published| published <= seconds)
+                .unwrap_or(false)
+        })
+        .max_by(|a, b| a.vers.cmp(&b.vers))
+}
+
+/// Returns whether any of the versions matching `requirement` have no publish
+/// time, in which case [`latest_published_before`] may have missed them.
+pub fn has_undated_versions(versions: &[IndexVersion], requirement: &semver::VersionReq) -> bool {
+    versions.iter().any(|version| {
+        !version.yanked
+            && requirement.matches(&version.vers)
+            && version.published_seconds().is_none()
+    })
+}
+
+/// Parses a UTC timestamp like `2014-12-05T20:20:39Z` into seconds since the
+/// unix epoch.
+pub fn parse_utc_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':');
+    let (hour, minute) = (
+        time.next()?.parse::<i64>().ok()?,
+        time.next()?.parse::<i64>().ok()?,
+    );
+    let second = time.next()?.split('.').next()?.parse::<i64>().ok()?;
+
+    // Days since the epoch, from Howard Hinnant's `days_from_civil` algorithm.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok()
+}
+
+/// Returns the path of a package's file within the index, relative to its root.
+pub fn index_path(package: &str) -> PathBuf {
+    let package = package.to_lowercase();
+    match package.len() {
+        1 => Path::new("1").join(&package),
+        2 => Path::new("2").join(&package),
+        3 => Path::new("3").join(&package[..1]).join(&package),
+        _ => Path::new(&package[..2]).join(&package[2..4]).join(&package),
+    }
+}
+
+/// Parses one of cargo's index cache files: a version byte, a little-endian
+/// `u32` index format version, and a null-terminated revision, followed by
+/// null-terminated pairs of version numbers and JSON index entries.
+pub fn parse_index_cache(bytes: &[u8]) -> Result<Vec<IndexVersion>> {
+    match bytes.first() {
+        Some(3) => {}
+        Some(version) => eyre::bail!("unsupported index cache version {version}"),
+        None => eyre::bail!("empty index cache"),
+    }
+    if bytes.len() < 5 {
+        eyre::bail!("truncated index cache");
+    }
+
+    let mut fields = bytes[5..].split(|byte| *byte == 0);
+    fields.next();
+
+    let mut versions = Vec::new();
+    while let (Some(_), Some(json)) = (fields.next(), fields.next()) {
+        match serde_json::from_slice(json) {
+            Ok(version) => versions.push(version),
+            Err(err) => trace!("skipping unreadable index entry: {err}"),
+        }
+    }
+    Ok(versions)
+}
+
+#[test]
+fn test_parse_index_cache() {
+    let mut bytes = vec![3, 2, 0, 0, 0];
+    bytes.extend(b"etag: \"1234\"\0");
+    for (version, json) in [
+        (
+            "1.0.0",
+            r#"{"name": "Proc-Macro2", "vers": "1.0.0", "yanked": true}"#,
+        ),
+        (
+            "1.0.1",
+            r#"{"name": "Proc-Macro2", "vers": "1.0.1", "pubtime": "2020-01-01T00:00:00Z"}"#,
+        ),
+        (
+            "1.1.0-rc",
+            r#"{"name": "Proc-Macro2", "vers": "1.1.0-rc", "pubtime": "2020-06-01T00:00:00Z"}"#,
+        ),
+        (
+            "1.0.2",
+            r#"{"name": "Proc-Macro2", "vers": "1.0.2", "pubtime": "2021-01-01T00:00:00Z"}"#,
+        ),
+        ("x", r#"{"name": "Proc-Macro2", "vers": "x"}"#),
+    ] {
+        for field in [version, json] {
+            bytes.extend(field.as_bytes());
+            bytes.push(0);
+        }
+    }
+
+    let versions = parse_index_cache(&bytes).unwrap();
+    expect![[r#"
+        [
+            (
+                "Proc-Macro2",
+                "1.0.0",
+                true,
+            ),
+            (
+                "Proc-Macro2",
+                "1.0.1",
+                false,
+            ),
+            (
+                "Proc-Macro2",
+                "1.1.0-rc",
+                false,
+            ),
+            (
+                "Proc-Macro2",
+                "1.0.2",
+                false,
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(
+        &versions
+            .iter()
+            .map(|version| (&version.name, version.vers.to_string(), version.yanked))
+            .collect::<Vec<_>>(),
+    );
+
+    let latest = |timestamp| {
+        latest_published_before(
+            &versions,
+            &semver::VersionReq::STAR,
+            parse_utc_timestamp(timestamp).unwrap(),
+        )
+        .map(|version| version.vers.to_string())
+    };
+    assert_eq!(latest("2019-12-31T23:59:59Z"), None);
+    assert_eq!(latest("2020-12-31T00:00:00Z").as_deref(), Some("1.0.1"));
+    assert_eq!(latest("2022-01-01T00:00:00Z").as_deref(), Some("1.0.2"));
+
+    assert!(parse_index_cache(&[1, 0]).is_err());
+    assert_eq!(parse_utc_timestamp("1970-01-01T00:00:00Z"), Some(0));
+    assert_eq!(
+        parse_utc_timestamp("2014-11-04T12:00:00Z"),
+        Some(1_415_102_400)
+    );
+    assert_eq!(
+        parse_utc_timestamp("2024-02-29T23:59:59.5Z"),
+        Some(1_709_251_199)
+    );
+    assert_eq!(parse_utc_timestamp("2024-02-29 23:59:59"), None);
+    assert_eq!(index_path("Proc-Macro2"), Path::new("pr/oc/proc-macro2"));
+    assert_eq!(index_path("syn"), Path::new("3/s/syn"));
+    assert_eq!(index_path("cc"), Path::new("2/cc"));
+}
+
+#[test]
+fn test_latest_published_before() {
+    let versions: Vec<IndexVersion> = [
+        r#"{"name": "old", "vers": "0.9.0"}"#,
+        r#"{"name": "old", "vers": "1.0.0"}"#,
+        r#"{"name": "old", "vers": "1.0.1"}"#,
+        r#"{"name": "old", "vers": "1.0.2", "pubtime": "2025-01-01T00:00:00Z"}"#,
+        r#"{"name": "old", "vers": "1.1.0", "pubtime": "2025-06-01T00:00:00Z"}"#,
+    ]
+    .iter()
+    .map(|json| serde_json::from_str(json).unwrap())
+    .collect();
+
+    let latest = |requirement: &str, timestamp| {
+        latest_published_before(
+            &versions,
+            &requirement.parse().unwrap(),
+            parse_utc_timestamp(timestamp).unwrap(),
+        )
+        .map(|version| version.vers.to_string())
+    };
+    // Undated versions were published before the earliest dated one...
+    assert_eq!(
+        latest("*", "2025-01-01T00:00:00Z").as_deref(),
+        Some("1.0.2")
+    );
+    assert_eq!(
+        latest("<1.0.2", "2025-01-01T00:00:00Z").as_deref(),
+        Some("1.0.1")
+    );
+    assert_eq!(
+        latest("^0.9", "2025-03-01T00:00:00Z").as_deref(),
+        Some("0.9.0")
+    );
+    assert_eq!(
+        latest("*", "2025-07-01T00:00:00Z").as_deref(),
+        Some("1.1.0")
+    );
+    // ...but we can't tell how long before.
+    assert_eq!(latest("*", "2024-12-31T23:59:59Z"), None);
+    assert!(has_undated_versions(&versions, &"^1".parse().unwrap()));
+    assert!(!has_undated_versions(
+        &versions,
+        &">=1.0.2".parse().unwrap()
+    ));
+
+    let undated = &versions[..3];
+    assert_eq!(published_by(&undated[0], undated), None);
+    assert!(latest_published_before(undated, &semver::VersionReq::STAR, u64::MAX).is_none());
+}
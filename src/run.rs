@@ -2,201 +2,899 @@
 
 use {
     crate::*,
-    ::std::{fs, process::Command},
+    ::eyre::WrapErr,
+    ::std::{
+        collections::BTreeMap,
+        fs,
+        io::Write,
+        path::Path,
+        process::{Command, Stdio},
+        sync::Arc,
+    },
 };
 
-pub fn compile_and_run(path: PathBuf, body: String, args: &[OsString]) -> Result<()> {
-    let data_dir = ::home::home_dir().unwrap_or_default().join(".rust-exe");
-    let src_dir = data_dir.join("src");
-    let bin_dir = data_dir.join("bin");
-    let tmp_dir = std::env::temp_dir().join("rust-exe");
+/// The exit status used when a script fails to compile, so that callers can
+/// tell that apart from the script running and exiting with an error. This is
+/// `EX_DATAERR` from `sysexits.h`, which unlike 101 (a Rust panic), 126, 127
+/// and 128 and up (signals) isn't reserved by Rust or the shell.
+pub const COMPILE_ERROR_EXIT_CODE: i32 = 65;
 
-    fs::create_dir_all(&src_dir).unwrap();
-    fs::create_dir_all(&tmp_dir).unwrap();
-    fs::create_dir_all(&bin_dir).unwrap();
+/// The error returned when cargo fails to build a script. Cargo will have
+/// already printed the relevant diagnostics.
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub path: PathBuf,
+}
 
-    let _mtime = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to compile {}", self.path.display())
+    }
+}
 
-    let hash = git_hashing::git_blob_sha1_hex(body.as_bytes());
-    let hash8 = &hash[..8];
+impl std::error::Error for CompileError {}
 
-    let path_hash = git_hashing::git_blob_sha1_hex(path.as_os_str().as_bytes());
-    let path8 = &path_hash[..8];
+/// The crate that we generate for a script, with everything needed to build it.
+#[derive(Debug, Clone)]
+pub struct ScriptCrate {
+    /// The path of the script, as it was given to us.
+    pub path: PathBuf,
+    pub dirs: DataDirs,
+    /// The `{kebab}-{path8}` name of the crate and its binary.
+    pub crate_name: String,
+    pub crate_path: PathBuf,
+    /// The git hash of the script's source files.
+    pub content_hash: String,
+    /// A hash of everything that goes into the binary.
+    pub build_hash: String,
+    /// A hash of the inputs that [`cached_binary`] checks; see [`input_hash`].
+    pub input_hash: String,
+    pub manifest: String,
+    /// The version requirements that we inferred for the script's dependencies,
+    /// by their names in the manifest.
+    pub inferred_versions: BTreeMap<String, String>,
+    pub source_map: SourceMap,
+    pub toolchain: Toolchain,
+    /// The cargo profile to build the binary with, `dev` unless the script's
+    /// `[package.metadata.rust-exe]` says otherwise.
+    pub profile: String,
+    /// The profile that the script's metadata chooses, even if `profile` has
+    /// been changed since.
+    pub script_profile: String,
+    /// The paths of the script's module files.
+    pub modules: Vec<PathBuf>,
+    /// When the script was last modified, in seconds since the unix epoch.
+    pub mtime: u64,
+    /// The source files of the crate, relative to `crate_path`.
+    pub files: Vec<(PathBuf, String)>,
+    registry: Registry,
+    /// A shared lock on the crate's cached files, which keeps garbage
+    /// collection from removing them while we're using them.
+    lock: Arc<fs::File>,
+}
 
-    let name = path.as_path().file_stem().unwrap().to_string_lossy();
-    let snake = name.to_snake_case();
-    let kebab = name.to_kebab_case();
-    let filename = format!("{snake}.rs");
+impl ScriptCrate {
+    /// Works out the crate for a script, without writing it anywhere yet.
+    pub fn generate(path: PathBuf, body: &str) -> Result<Self> {
+        let dirs = DataDirs::new()?;
+        let config = Config::load(&dirs.data_dir)?;
 
-    let version = format!("0.0.0-{hash8}");
+        let name = path.as_path().file_stem().unwrap().to_string_lossy();
+        let snake = name.to_snake_case();
+        let filename = format!("{snake}.rs");
 
-    let crate_name = format!("{kebab}-{path8}");
-    let crate_path = src_dir.join(&crate_name);
+        let (source, frontmatter) = strip_frontmatter(body)?;
 
-    fs::remove_dir_all(&crate_path).ok();
-    fs::create_dir_all(&crate_path).unwrap();
+        let file = parse_source(&source)?;
+        let modules = discover_modules(&path, &file)?;
 
-    let mut manifest = toml! {
-        [package]
-        autobins = false
-        edition = "2021"
-        name = (crate_name.clone())
-        version = version
+        // The script's files are resolved against the registry as it was when they
+        // were last modified. Scripts without files, like `eval`s, use the present.
+        let mtime = std::iter::once(&path)
+            .chain(modules.iter().map(|module| &module.path))
+            .filter_map(|path| {
+                fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+            .max()
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
 
-        [[bin]]
-        name = (crate_name.clone())
-        path = (filename.clone())
+        let hash = if modules.is_empty() {
+            git_hashing::git_blob_sha1_hex(body.as_bytes())
+        } else {
+            git_hashing::git_tree_sha1_hex(
+                std::iter::once((filename.clone(), body.as_bytes())).chain(modules.iter().map(
+                    |module| {
+                        (
+                            module.relative_path.to_string_lossy().into_owned(),
+                            module.body.as_bytes(),
+                        )
+                    },
+                )),
+            )
+        };
+        let hash8 = &hash[..8];
 
-        [dependencies]
-    };
+        let module_paths = modules
+            .iter()
+            .map(|module| module.path.clone())
+            .collect::<Vec<_>>();
+        let input_hash = input_hash(&dirs, &path, body, &module_paths).unwrap_or_default();
 
-    let file = syn::parse_file(&body)?;
-    let mut crate_doc = String::new();
+        let version = format!("0.0.0-{hash8}");
 
-    for attr in file.attrs.iter() {
-        if attr.path.is_ident("docs") {
-            if let Ok(syn::Meta::NameValue(meta)) = attr.parse_meta() {
-                if let syn::Lit::Str(lit) = meta.lit {
-                    crate_doc.push_str(&lit.value());
-                    crate_doc.push('\n');
-                }
-            }
+        let crate_name = script_crate_name(&path);
+        let crate_path = dirs.src_dir.join(&crate_name);
+        let lock = Arc::new(lock_crate(&dirs.index_dir, &crate_name)?);
+
+        let root_dir = module_root_dir(&path, &modules)?;
+        let main_path = root_dir.join(&filename);
+
+        let mut source_map = SourceMap::new(&crate_path);
+        source_map.insert(&main_path, &path);
+        for module in modules.iter() {
+            source_map.insert(
+                normalize(&root_dir.join(&module.relative_path)),
+                &module.path,
+            );
         }
-    }
 
-    let root_crates = {
-        impl<'ast> syn::visit::Visit<'ast> for Visitor {
-            fn visit_path(&mut self, path: &'ast syn::Path) {
-                if path.leading_colon.is_some() {
-                    let root_crate = path.segments.first().unwrap().ident.to_string();
-                    self.root_crates.insert(root_crate);
-                }
-                syn::visit::visit_path(self, path);
-            }
+        let mut manifest = toml! {
+            [package]
+            autobins = false
+            name = (crate_name.clone())
+            version = version
 
-            fn visit_item_extern_crate(&mut self, item: &'ast syn::ItemExternCrate) {
-                if item.rename.is_some() {
-                    todo!("extern crate with rename not supported");
-                }
-                self.root_crates.insert(item.ident.to_string());
+            [[bin]]
+            name = (crate_name.clone())
+            path = (main_path.to_string_lossy().into_owned())
+
+            [dependencies]
+        };
+
+        let registry = Registry::open();
+        let dependencies = resolve_packages(
+            infer_dependencies(
+                std::iter::once(&file).chain(modules.iter().map(|module| &module.file)),
+            ),
+            &registry,
+            mtime,
+        );
+
+        let mut inferred_versions = BTreeMap::new();
+        for (root_crate, dependency) in dependencies {
+            let version = dependency
+                .version
+                .as_ref()
+                .map(|version| version.to_string())
+                .unwrap_or_else(|| "*".to_string());
+            inferred_versions.insert(root_crate.clone(), version.clone());
+
+            let mut entry = toml! {
+                version = version
+            };
+
+            let features = required_features(&dependency, &config);
+            if !features.is_empty() {
+                entry.as_table_mut().unwrap().insert(
+                    "features".to_string(),
+                    features.into_iter().collect::<Vec<_>>().into(),
+                );
             }
 
-            fn visit_item_use(&mut self, item_use: &'ast syn::ItemUse) {
-                if item_use.leading_colon.is_some() {
-                    match &item_use.tree {
-                        syn::UseTree::Path(syn::UsePath { ident, .. })
-                        | syn::UseTree::Name(syn::UseName { ident, .. })
-                        | syn::UseTree::Rename(syn::UseRename { ident, .. }) => {
-                            self.root_crates.insert(ident.to_string());
-                        }
-                        syn::UseTree::Group(group) => {
-                            for tree in group.items.iter() {
-                                match tree {
-                                    syn::UseTree::Path(syn::UsePath { ident, .. })
-                                    | syn::UseTree::Name(syn::UseName { ident, .. })
-                                    | syn::UseTree::Rename(syn::UseRename { ident, .. }) => {
-                                        self.root_crates.insert(ident.to_string());
-                                    }
-                                    syn::UseTree::Glob(_) => todo!(),
-                                    syn::UseTree::Group(_) => todo!(),
-                                }
-                            }
-                        }
-                        syn::UseTree::Glob(_) => {
-                            eprintln!("This is weird and unexpected: {item_use:?}.");
-                        }
-                    }
-                } else {
-                    match &item_use.tree {
-                        syn::UseTree::Group(group) => {
-                            for _tree in group.items.iter() {
-                                // we need to support cases like
-                                // use {{{::{{{{crossterm::style::
-                                // {{{{Stylize}}}}}}}}}}};
-                            }
-                        }
-                        syn::UseTree::Name(_) => {}
-                        _ => {}
-                    }
-                }
-                syn::visit::visit_item_use(self, item_use);
+            if let Some(package) = dependency.package {
+                entry
+                    .as_table_mut()
+                    .unwrap()
+                    .insert("package".to_string(), package.into());
             }
+
+            manifest["dependencies"]
+                .as_table_mut()
+                .unwrap()
+                .insert(root_crate, entry);
         }
-        #[derive(Default)]
-        struct Visitor {
-            root_crates: std::collections::BTreeSet<String>,
+
+        if let Some(doc_manifest) = doc_manifest(&file.syntax)? {
+            merge_toml(&mut manifest, doc_manifest);
         }
-        let mut visitor = Visitor::default();
-        syn::visit::visit_file(&mut visitor, &file);
 
-        visitor.root_crates
-    };
+        if let Some(frontmatter) = frontmatter {
+            merge_toml(&mut manifest, frontmatter);
+        }
 
-    let builtin_crates: std::collections::BTreeSet<String> =
-        ["core", "alloc", "std", "proc_macro", "test"]
-            .iter()
-            .map(|s| s.to_string())
-            .collect();
+        let profile = match manifest["package"]
+            .get("metadata")
+            .and_then(|metadata| metadata.get("rust-exe"))
+            .and_then(|metadata| metadata.get("profile"))
+        {
+            Some(profile) => profile
+                .as_str()
+                .ok_or_else(|| eyre::eyre!("expected the profile to be a string, found {profile}"))?
+                .to_string(),
+            None => "dev".to_string(),
+        };
 
-    for root_crate in root_crates {
-        if builtin_crates.contains(&root_crate) {
-            continue;
+        let rust_version = manifest["package"]
+            .get("rust-version")
+            .and_then(Toml::as_str);
+        let release = script_release(rust_version, mtime)?;
+        let feature_gates = feature_gates(&file.syntax);
+        let toolchain = if feature_gates.is_empty() {
+            Toolchain::select(release, &dirs.src_dir)?
+        } else {
+            debug!("using nightly for unstable features: {feature_gates:?}");
+            Toolchain::nightly(&dirs.src_dir).wrap_err_with(|| {
+                format!(
+                    "{} uses unstable features ({}), which require a nightly toolchain",
+                    path.display(),
+                    feature_gates.join(", ")
+                )
+            })?
+        };
+        debug!("using toolchain {:?}", toolchain.name);
+
+        let rustc_version = toolchain.rustc_version_verbose(&dirs.src_dir)?;
+
+        if manifest["package"].get("edition").is_none() {
+            let edition = edition_at_release(release.min(rustc_release(&rustc_version)?));
+            manifest["package"]
+                .as_table_mut()
+                .unwrap()
+                .insert("edition".to_string(), edition.to_string().into());
         }
 
-        manifest["dependencies"].as_table_mut().unwrap().insert(
-            root_crate.clone(),
-            toml! {
-                version = "*"
-            },
+        let manifest = manifest.to_string();
+        // The verbose version information includes the commit date of nightlies,
+        // so updating the nightly toolchain also invalidates the cached binary.
+        let toolchain_name = toolchain.name.as_deref().unwrap_or("default");
+        let build_hash = git_hashing::git_blob_sha1_hex(
+            format!("{hash}\n{manifest}\n{toolchain_name}\n{rustc_version}").as_bytes(),
         );
+
+        let mut files = vec![(main_path, source)];
+        for module in modules {
+            files.push((
+                normalize(&root_dir.join(&module.relative_path)),
+                module.body,
+            ));
+        }
+
+        Ok(ScriptCrate {
+            path,
+            dirs,
+            crate_name,
+            crate_path,
+            content_hash: hash,
+            build_hash,
+            input_hash,
+            manifest,
+            inferred_versions,
+            source_map,
+            toolchain,
+            script_profile: profile.clone(),
+            profile,
+            modules: module_paths,
+            mtime,
+            files,
+            registry,
+            lock,
+        })
     }
 
-    let manifest_path = crate_path.join("Cargo.toml");
-    std::fs::write(&manifest_path, manifest.to_string())?;
-    let main_path = crate_path.join(filename);
-    std::fs::write(main_path, body)?;
-
-    Command::new("cargo")
-        .args(&["build", "--quiet", "--target-dir"])
-        .arg(&tmp_dir)
-        .current_dir(&crate_path)
-        .status()?;
-
-    let lockfile = Lockfile::load(crate_path.join("Cargo.lock")).unwrap();
-    let package_lock = &lockfile
-        .packages
-        .iter()
-        .find(|p| p.name.as_str() == crate_name)
-        .unwrap();
-    let _dependencies = &package_lock
-        .dependencies
-        .iter()
-        .map(|d| format!("{} {}", d.name, d.version))
+    /// Writes the crate to its directory, replacing any previous version, and
+    /// locks its dependencies.
+    pub fn write(&self) -> Result<()> {
+        fs::remove_dir_all(&self.crate_path).ok();
+        fs::create_dir_all(&self.crate_path)?;
+
+        fs::write(self.crate_path.join("Cargo.toml"), &self.manifest)?;
+        for (path, body) in self.files.iter() {
+            let path = self.crate_path.join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, body)?;
+        }
+
+        if let Some(missing) = &self.toolchain.missing {
+            warn!(
+                "Rust {missing} isn't installed, using the default toolchain instead. \
+                 Install it with `rustup toolchain install {missing}`."
+            );
+        }
+
+        if let Err(err) = time_travel_lockfile(
+            &self.crate_path,
+            &self.toolchain,
+            &self.registry,
+            self.mtime,
+        ) {
+            debug!("unable to lock dependencies as of {}: {err}", self.mtime);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path of the script's binary, building it first unless the
+    /// cached binary is up to date.
+    pub fn build(&self) -> Result<PathBuf> {
+        let binary_name = binary_name(&self.crate_name, &self.profile);
+        let binary = self.dirs.bin_dir.join(&binary_name);
+
+        let cached = CacheEntry::load(&self.dirs.index_dir, &binary_name)
+            .filter(|entry| entry.build_hash == self.build_hash && binary.exists());
+
+        if let Some(mut entry) = cached {
+            debug!(
+                "using cached binary for {binary_name} ({})",
+                &self.build_hash[..8]
+            );
+            entry.last_used = now_seconds();
+            entry.script_profile = self.script_profile.clone();
+            entry.modules = self.modules.clone();
+            entry.input_hash = self.input_hash.clone();
+            entry.save(&self.dirs.index_dir)?;
+            return Ok(binary);
+        }
+
+        self.write()?;
+
+        // Older versions of cargo only support the built-in profiles, using
+        // `--release` rather than `--profile`.
+        let profile_args = match self.profile.as_str() {
+            "dev" => vec![],
+            "release" => vec!["--release"],
+            profile => vec!["--profile", profile],
+        };
+
+        TargetEntry::touch(&self.dirs.index_dir, &self.profile)?;
+        let output = run_cargo(
+            self.toolchain
+                .cargo()
+                .args(["build", "--quiet"])
+                .args(profile_args)
+                .arg("--target-dir")
+                .arg(&self.dirs.tmp_dir)
+                .current_dir(&self.crate_path),
+            &self.source_map,
+        )
+        .wrap_err("failed to run cargo")?;
+
+        if !output.status.success() {
+            return Err(CompileError {
+                path: self.path.clone(),
+            }
+            .into());
+        }
+
+        match read_lockfile(&self.crate_path.join("Cargo.lock")) {
+            Ok(packages) => {
+                let dependencies = packages
+                    .iter()
+                    .find(|package| package.name == self.crate_name)
+                    .map(|package| package.dependencies.clone())
+                    .unwrap_or_default();
+                debug!("locked dependencies: {dependencies:?}");
+            }
+            Err(err) => debug!("unable to read Cargo.lock: {err}"),
+        }
+
+        fs::copy(
+            self.dirs
+                .tmp_dir
+                .join(profile_dir(&self.profile))
+                .join(&self.crate_name),
+            &binary,
+        )
+        .wrap_err_with(|| format!("failed to copy binary for {}", self.path.display()))?;
+
+        CacheEntry {
+            crate_name: self.crate_name.clone(),
+            profile: self.profile.clone(),
+            source_path: self.path.clone(),
+            content_hash: self.content_hash.clone(),
+            build_hash: self.build_hash.clone(),
+            last_used: now_seconds(),
+            script_profile: self.script_profile.clone(),
+            modules: self.modules.clone(),
+            input_hash: self.input_hash.clone(),
+        }
+        .save(&self.dirs.index_dir)?;
+
+        Ok(binary)
+    }
+}
+
+/// Returns the `{kebab}-{path8}` name of the crate generated for a script,
+/// which depends on the path that the script was given as.
+pub fn script_crate_name(path: &Path) -> String {
+    let kebab = path.file_stem().unwrap().to_string_lossy().to_kebab_case();
+    let path_hash = git_hashing::git_blob_sha1_hex(path.as_os_str().as_bytes());
+    format!("{kebab}-{}", &path_hash[..8])
+}
+
+/// Hashes the inputs of a script's build that we can check without parsing it
+/// or running any tools: its files and when they were last modified, our
+/// configuration, and the installed toolchains. Returns `None` if one of its
+/// module files can't be read.
+pub fn input_hash(dirs: &DataDirs, path: &Path, body: &str, modules: &[PathBuf]) -> Option<String> {
+    let mut input = format!(
+        "{}\n{}\n",
+        path.display(),
+        git_hashing::git_blob_sha1_hex(body.as_bytes())
+    );
+    for module in modules {
+        let module_body = fs::read(module).ok()?;
+        input += &format!(
+            "{}\n{}\n",
+            module.display(),
+            git_hashing::git_blob_sha1_hex(&module_body)
+        );
+    }
+
+    let mtime = std::iter::once(path)
+        .chain(modules.iter().map(PathBuf::as_path))
+        .filter_map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .max()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs());
+    let config = fs::read_to_string(Config::path(&dirs.data_dir)).unwrap_or_default();
+    input += &format!("{mtime}\n{config}\n{}\n", toolchains_fingerprint());
+
+    Some(git_hashing::git_blob_sha1_hex(input.as_bytes()))
+}
+
+/// Returns the cached binary of a script, built with `profile` or else with the
+/// profile its metadata chooses, if none of its inputs have changed since it
+/// was built. This only reads files, so that running a script that's already
+/// been built doesn't wait on the toolchain or the registry. The returned lock
+/// keeps garbage collection from removing the binary while it's in use.
+pub fn cached_binary(
+    path: &Path,
+    body: &str,
+    profile: Option<&str>,
+) -> Result<Option<(PathBuf, fs::File)>> {
+    let dirs = DataDirs::new()?;
+    let crate_name = script_crate_name(path);
+    let lock = lock_crate(&dirs.index_dir, &crate_name)?;
+
+    let unchanged = CacheEntry::load_crate(&dirs.index_dir, &crate_name)
+        .into_iter()
+        .filter(|entry| {
+            !entry.input_hash.is_empty()
+                && input_hash(&dirs, path, body, &entry.modules).as_ref() == Some(&entry.input_hash)
+        })
         .collect::<Vec<_>>();
 
-    std::fs::copy(
-        tmp_dir.join("debug").join(&crate_name),
-        bin_dir.join(&crate_name),
-    )?;
+    let profile =
+        match profile.or_else(|| unchanged.first().map(|entry| entry.script_profile.as_str())) {
+            Some(profile) => profile.to_string(),
+            None => return Ok(None),
+        };
+    let binary_name = binary_name(&crate_name, &profile);
+    let binary = dirs.bin_dir.join(&binary_name);
+    let mut entry = match unchanged.into_iter().find(|entry| entry.profile == profile) {
+        Some(entry) if binary.exists() => entry,
+        _ => return Ok(None),
+    };
 
-    let status = Command::new(bin_dir.join(&crate_name))
+    debug!(
+        "using cached binary for {binary_name} ({})",
+        entry.build_hash.get(..8).unwrap_or(&entry.build_hash)
+    );
+    entry.last_used = now_seconds();
+    entry.save(&dirs.index_dir)?;
+    Ok(Some((binary, lock)))
+}
+
+pub fn compile_and_run(
+    path: PathBuf,
+    body: String,
+    profile: Option<String>,
+    args: &[OsString],
+) -> Result<()> {
+    // The cache is checked before generating the crate, which needs the
+    // toolchain and the registry.
+    let (binary, _lock) = match cached_binary(&path, &body, profile.as_deref())? {
+        Some((binary, lock)) => (binary, Arc::new(lock)),
+        None => {
+            let mut script = ScriptCrate::generate(path, &body)?;
+            if let Some(profile) = profile {
+                script.profile = profile;
+            }
+            (script.build()?, Arc::clone(&script.lock))
+        }
+    };
+
+    let status = Command::new(binary)
         .args(args)
         .status()?
         .code()
         .unwrap_or(0xFF);
 
-    Command::new("find")
-        .arg(src_dir)
-        .args(["-mmin", "32", "-delete"])
-        .status()?;
-    Command::new("find")
-        .arg(tmp_dir)
-        .args(["-atime", "2", "-delete"])
-        .status()?;
-    Command::new("find")
-        .arg(bin_dir)
-        .args(["-atime", "8", "-delete"])
-        .status()?;
+    spawn_background_gc(&DataDirs::new()?);
+
+    std::process::exit(status);
+}
+
+/// Builds a script's tests and runs them, passing `args` (such as test name
+/// filters) to the test harness, then exits with its status.
+pub fn compile_and_test(path: PathBuf, body: String, args: &[OsString]) -> Result<()> {
+    let script = ScriptCrate::generate(path, &body)?;
+    script.write()?;
+
+    TargetEntry::touch(&script.dirs.index_dir, "test")?;
+    let output = run_cargo(
+        script
+            .toolchain
+            .cargo()
+            .args(["test", "--quiet", "--no-run", "--target-dir"])
+            .arg(&script.dirs.tmp_dir)
+            .current_dir(&script.crate_path),
+        &script.source_map,
+    )
+    .wrap_err("failed to run cargo")?;
+
+    if !output.status.success() {
+        return Err(CompileError { path: script.path }.into());
+    }
+
+    let mut status = 0;
+    for executable in output.executables {
+        let code = run_remapped(Command::new(executable).args(args), &script.source_map)?
+            .code()
+            .unwrap_or(0xFF);
+        if status == 0 {
+            status = code;
+        }
+    }
+
+    spawn_background_gc(&script.dirs);
 
     std::process::exit(status);
 }
+
+/// Runs `cargo check` or `cargo clippy` on a script's crate without building
+/// or running it, passing `args` through to cargo, then exits with its status.
+pub fn compile_and_check(
+    path: PathBuf,
+    body: String,
+    subcommand: &str,
+    args: &[OsString],
+) -> Result<()> {
+    let script = ScriptCrate::generate(path, &body)?;
+    script.write()?;
+
+    TargetEntry::touch(&script.dirs.index_dir, "dev")?;
+    let output = run_cargo_with(
+        script
+            .toolchain
+            .cargo()
+            .args([subcommand, "--quiet", "--target-dir"])
+            .arg(&script.dirs.tmp_dir)
+            .current_dir(&script.crate_path),
+        args,
+        &script.source_map,
+    )
+    .wrap_err("failed to run cargo")?;
+
+    spawn_background_gc(&script.dirs);
+
+    std::process::exit(output.status.code().unwrap_or(0xFF));
+}
+
+/// Formats a script with rustfmt, leaving its shebang line and frontmatter
+/// as they are. With `check`, the script is left unchanged and we exit with an
+/// error status if it isn't already formatted.
+pub fn format_script(path: PathBuf, body: String, check: bool) -> Result<()> {
+    let edition = script_edition(&path, &body)?;
+
+    // rustfmt drops leading blank lines, so we put back the ones between the
+    // header and the source.
+    let (header, source) = split_header(&body)?;
+    let indent = source.len() - source.trim_start().len();
+    let separator = match source[..indent].rfind('\n') {
+        Some(newline) if !header.is_empty() => &source[..=newline],
+        _ => "",
+    };
+    let mut formatted = format!("{header}{separator}");
+    formatted.push_str(&rustfmt(&path, &edition, source)?);
+
+    // rustfmt leaves doc comments alone by default, but a configuration that
+    // wraps or reformats them could break the script's manifest.
+    let manifest_block = |body: &str| -> Result<Option<String>> {
+        let (source, _) = strip_frontmatter(body)?;
+        Ok(cargo_block(&crate_doc(&parse_source(&source)?.syntax)))
+    };
+    if manifest_block(&formatted)? != manifest_block(&body)? {
+        eyre::bail!(
+            "rustfmt changed the manifest in the docs of {}, so it was left unformatted",
+            path.display()
+        );
+    }
+
+    if formatted == body {
+        return Ok(());
+    }
+
+    if check {
+        eprintln!("{} is not formatted", path.display());
+        std::process::exit(1);
+    }
+
+    fs::write(&path, formatted).wrap_err_with(|| format!("failed to write {}", path.display()))
+}
+
+/// Returns the edition that a script is written for: the one its manifest sets,
+/// or else the one that was current when its files were last modified. Unlike
+/// [`ScriptCrate::generate`], this doesn't consult the toolchain or registry.
+fn script_edition(path: &Path, body: &str) -> Result<String> {
+    let (source, frontmatter) = strip_frontmatter(body)?;
+    let file = parse_source(&source)?;
+
+    let mut manifest = toml! {
+        [package]
+    };
+    if let Some(doc_manifest) = doc_manifest(&file.syntax)? {
+        merge_toml(&mut manifest, doc_manifest);
+    }
+    if let Some(frontmatter) = frontmatter {
+        merge_toml(&mut manifest, frontmatter);
+    }
+
+    if let Some(edition) = manifest["package"].get("edition").and_then(Toml::as_str) {
+        return Ok(edition.to_string());
+    }
+
+    let modules = discover_modules(path, &file)?;
+    let mtime = std::iter::once(path)
+        .chain(modules.iter().map(|module| module.path.as_path()))
+        .filter_map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .max()
+        .unwrap_or_else(SystemTime::now)
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+    let rust_version = manifest["package"]
+        .get("rust-version")
+        .and_then(Toml::as_str);
+    Ok(edition_at_release(script_release(rust_version, mtime)?).to_string())
+}
+
+/// Runs rustfmt on the Rust source of a script, from the script's directory so
+/// that any `rustfmt.toml` alongside it applies.
+fn rustfmt(path: &Path, edition: &str, source: &str) -> Result<String> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    let mut child = Command::new("rustfmt")
+        .args(["--edition", edition, "--emit", "stdout"])
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .wrap_err("failed to run rustfmt")?;
+    child.stdin.take().unwrap().write_all(source.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        eyre::bail!("rustfmt failed to format {}", path.display());
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Applies the compiler's machine-applicable suggestions to a script with
+/// `cargo fix`, passing `args` through to cargo, and copies the fixed source
+/// back over the script's files.
+pub fn fix_script(path: PathBuf, body: String, args: &[OsString]) -> Result<()> {
+    let script = ScriptCrate::generate(path, &body)?;
+    script.write()?;
+
+    TargetEntry::touch(&script.dirs.index_dir, "dev")?;
+    let output = run_cargo_with(
+        script
+            .toolchain
+            .cargo()
+            // The generated crate may be inside a git work tree, such as a home
+            // directory under version control, where it'd look dirty. It's
+            // ours to change, so that doesn't matter.
+            .args(["fix", "--quiet", "--allow-no-vcs", "--allow-dirty"])
+            .args(["--allow-staged", "--target-dir"])
+            .arg(&script.dirs.tmp_dir)
+            .current_dir(&script.crate_path),
+        args,
+        &script.source_map,
+    )
+    .wrap_err("failed to run cargo")?;
+
+    spawn_background_gc(&script.dirs);
+
+    if !output.status.success() {
+        return Err(CompileError { path: script.path }.into());
+    }
+
+    // The generated copy of the script has the same header lines: the shebang
+    // is copied through and the frontmatter is blanked out line for line. So
+    // the original header can be put back in place of those lines.
+    let (header, _) = split_header(&body)?;
+    let header_lines = header.split_inclusive('\n').count();
+
+    let mut fixed = Vec::new();
+    for (index, (generated, source)) in script.files.iter().enumerate() {
+        let current = fs::read_to_string(script.crate_path.join(generated))?;
+        if current == *source {
+            continue;
+        }
+
+        let original = script.source_map.original(generated).unwrap();
+        let current = if index == 0 {
+            let blanked_len = source
+                .split_inclusive('\n')
+                .take(header_lines)
+                .map(str::len)
+                .sum();
+            match current.strip_prefix(&source[..blanked_len]) {
+                Some(rest) => format!("{header}{rest}"),
+                None => eyre::bail!(
+                    "cargo fix changed the shebang or frontmatter of {}",
+                    original.display()
+                ),
+            }
+        } else {
+            current
+        };
+
+        let expected = if index == 0 { &body } else { source };
+        if fs::read_to_string(original).ok().as_ref() != Some(expected) {
+            eyre::bail!(
+                "{} changed while it was being fixed, so the fixes weren't written back",
+                original.display()
+            );
+        }
+
+        fixed.push((original, current));
+    }
+
+    for (original, current) in fixed {
+        fs::write(original, current)
+            .wrap_err_with(|| format!("failed to write {}", original.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the crate generated for a script into `dir` as a standalone Cargo
+/// project, with its dependencies locked to the versions we would build with.
+pub fn export_script(path: PathBuf, body: String, dir: PathBuf) -> Result<()> {
+    if fs::read_dir(&dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false)
+    {
+        eyre::bail!("{} already exists and isn't empty", dir.display());
+    }
+
+    let script = ScriptCrate::generate(path, &body)?;
+    script.write()?;
+
+    let lockfile_path = script.crate_path.join("Cargo.lock");
+    if !lockfile_path.exists()
+        && !script
+            .toolchain
+            .cargo()
+            .args(["generate-lockfile", "--quiet"])
+            .current_dir(&script.crate_path)
+            .status()?
+            .success()
+    {
+        eyre::bail!(
+            "failed to lock the dependencies of {}",
+            script.path.display()
+        );
+    }
+    let locked = read_lockfile(&lockfile_path)?;
+
+    let name = script
+        .path
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .to_kebab_case();
+
+    let mut manifest: Toml = toml_edit::easy::from_str(&script.manifest)?;
+    let generated_version = manifest["package"]["version"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let package = manifest["package"].as_table_mut().unwrap();
+    package.remove("autobins");
+    package.insert("name".to_string(), name.clone().into());
+    package.insert("version".to_string(), "0.1.0".into());
+
+    // The script's files keep their layout under `src`, with the script itself
+    // renamed to `main.rs` so that its module paths resolve the same way. Its
+    // shebang and manifest are left out, since the manifest is in Cargo.toml.
+    let (_, main_source) = split_header(&body)?;
+    let main_source = strip_cargo_block(main_source.trim_start_matches(['\r', '\n']));
+    let mut files = Vec::new();
+    for (index, (generated, source)) in script.files.iter().enumerate() {
+        if index == 0 {
+            files.push((
+                Path::new("src").join(generated.with_file_name("main.rs")),
+                main_source.as_str(),
+            ));
+        } else {
+            files.push((Path::new("src").join(generated), source.as_str()));
+        }
+    }
+
+    let table = manifest.as_table_mut().unwrap();
+    table.remove("bin");
+    let main_path = &files[0].0;
+    if main_path != Path::new("src/main.rs") {
+        table.insert(
+            "bin".to_string(),
+            Toml::Array(vec![toml! {
+                name = (name.clone())
+                path = (main_path.to_string_lossy().into_owned())
+            }]),
+        );
+    }
+
+    // Inferred requirements are replaced by the versions that were locked,
+    // which are easier to read and to update by hand.
+    if let Some(dependencies) = table.get_mut("dependencies").and_then(Toml::as_table_mut) {
+        for (key, inferred) in script.inferred_versions.iter() {
+            let entry = match dependencies.get_mut(key).and_then(Toml::as_table_mut) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if entry.get("version").and_then(Toml::as_str) != Some(inferred) {
+                continue;
+            }
+            let package = entry.get("package").and_then(Toml::as_str).unwrap_or(key);
+            let requirement = semver::VersionReq::parse(inferred)?;
+            let version = locked
+                .iter()
+                .filter(|locked| locked.name == package && requirement.matches(&locked.version))
+                .map(|locked| &locked.version)
+                .max();
+            if let Some(version) = version {
+                entry.insert("version".to_string(), version.to_string().into());
+                if entry.len() == 1 {
+                    dependencies.insert(key.clone(), version.to_string().into());
+                }
+            }
+        }
+    }
+
+    let lockfile = fs::read_to_string(&lockfile_path)?.replace(
+        &format!(
+            "name = \"{}\"\nversion = \"{generated_version}\"",
+            script.crate_name
+        ),
+        &format!("name = \"{name}\"\nversion = \"0.1.0\""),
+    );
+
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("Cargo.toml"), manifest.to_string())?;
+    fs::write(dir.join("Cargo.lock"), lockfile)?;
+    for (relative, source) in files {
+        let path = dir.join(relative);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, source)?;
+    }
+
+    Ok(())
+}
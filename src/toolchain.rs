@@ -1 +1,254 @@
+use {
+    crate::*,
+    ::once_cell::sync::Lazy,
+    ::regex::Regex,
+    ::std::{path::Path, process::Command},
+};
+
 mod releases;
+
+pub use self::releases::*;
+
+/// The toolchain that a script is built with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toolchain {
+    /// The name of a rustup toolchain, like `1.59.0`, or `None` to use the
+    /// default toolchain.
+    pub name: Option<String>,
+    /// The release that the script called for, like `1.59`, if it isn't
+    /// installed and we've fallen back to the default toolchain instead.
+    pub missing: Option<String>,
+}
+
+impl Toolchain {
+    /// Selects the stable toolchain for a script written for the Rust release
+    /// with the given minor version number (see [`script_release`]). Falls back
+    /// to the default toolchain if that release isn't installed, recording it
+    /// as `missing`.
+    pub fn select(minor: u64, dir: &Path) -> Result<Self> {
+        let default = Toolchain::default();
+        if minor >= rustc_release(&default.rustc_version_verbose(dir)?)? {
+            return Ok(default);
+        }
+
+        let installed = match rustup_toolchain_list(dir) {
+            Some(installed) => installed,
+            None => {
+                debug!("unable to list rustup toolchains, using the default toolchain");
+                return Ok(default);
+            }
+        };
+
+        match installed_release(&installed, minor) {
+            Some(name) => Ok(Toolchain {
+                name: Some(name),
+                missing: None,
+            }),
+            None => Ok(Toolchain {
+                missing: Some(format!("1.{minor}")),
+                ..default
+            }),
+        }
+    }
+
+    /// Selects an installed nightly toolchain, for scripts that use unstable
+    /// features, preferring the latest one that rustup tracks.
+    pub fn nightly(dir: &Path) -> Result<Self> {
+        if let Some(name) = rustup_toolchain_list(dir).and_then(|list| installed_nightly(&list)) {
+            return Ok(Toolchain {
+                name: Some(name),
+                missing: None,
+            });
+        }
+
+        let default = Toolchain::default();
+        if default.rustc_version_verbose(dir)?.contains("-nightly") {
+            return Ok(default);
+        }
+
+        eyre::bail!(
+            "no nightly toolchain is installed, install one with `rustup toolchain install nightly`"
+        )
+    }
+
+    /// Returns a `cargo` command that uses this toolchain.
+    pub fn cargo(&self) -> Command {
+        let mut command = Command::new("cargo");
+        if let Some(name) = &self.name {
+            command.arg(format!("+{name}"));
+        }
+        command
+    }
+
+    /// Returns the verbose version information (`rustc -vV`) of this toolchain,
+    /// as cargo would use it in `dir`.
+    pub fn rustc_version_verbose(&self, dir: &Path) -> Result<String> {
+        let mut command = Command::new("rustc");
+        if let Some(name) = &self.name {
+            command.arg(format!("+{name}"));
+        }
+        let output = command.arg("-vV").current_dir(dir).output()?;
+        if !output.status.success() {
+            eyre::bail!(
+                "failed to get rustc version: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+/// Returns the minor version number of the Rust release that a script was
+/// written for: the one named by its `rust-version`, if it has one, or else
+/// the latest release at `timestamp`.
+pub fn script_release(rust_version: Option<&str>, timestamp: u64) -> Result<u64> {
+    match rust_version {
+        Some(rust_version) => rust_version
+            .trim()
+            .strip_prefix("1.")
+            .and_then(|rest| rest.split('.').next())
+            .and_then(|minor| minor.parse().ok())
+            .ok_or_else(|| eyre::eyre!("invalid rust-version {rust_version:?}")),
+        None => Ok(last_release_at(timestamp)),
+    }
+}
+
+/// Returns the minor version number of a Rust release from its verbose version
+/// information (`rustc -vV`).
+pub fn rustc_release(version_verbose: &str) -> Result<u64> {
+    version_verbose
+        .lines()
+        .find_map(|line| line.strip_prefix("release: "))
+        .and_then(|release| release.split('.').nth(1))
+        .and_then(|minor| minor.parse().ok())
+        .ok_or_else(|| eyre::eyre!("unable to determine the rustc release"))
+}
+
+/// Returns a fingerprint of the installed toolchains that changes whenever one
+/// is installed, updated or removed, or the default changes. It only reads
+/// files, so it's much cheaper than asking rustup or rustc.
+pub fn toolchains_fingerprint() -> String {
+    let mtime = |path: &Path| {
+        std::fs::symlink_metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos())
+    };
+
+    let mut fingerprint = String::new();
+    for var in ["RUSTUP_TOOLCHAIN", "PATH"] {
+        let value = std::env::var_os(var).unwrap_or_default();
+        fingerprint += &format!("{var}={}\n", value.to_string_lossy());
+    }
+
+    // Toolchains installed without rustup are only found on the `PATH`.
+    if let Some(rustc) = std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join("rustc"))
+        .find(|rustc| rustc.exists())
+    {
+        fingerprint += &format!("{} {}\n", rustc.display(), mtime(&rustc));
+    }
+
+    let rustup_home = std::env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| ::home::home_dir().map(|home| home.join(".rustup")))
+        .unwrap_or_default();
+    fingerprint += &std::fs::read_to_string(rustup_home.join("settings.toml")).unwrap_or_default();
+
+    // rustup rewrites each toolchain's channel manifest when it installs or
+    // updates it. Linked toolchains don't have one, so we use the link instead.
+    let mut toolchains = std::fs::read_dir(rustup_home.join("toolchains"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect::<Vec<_>>();
+    toolchains.sort();
+    for toolchain in toolchains {
+        let manifest = toolchain.join("lib/rustlib/multirust-channel-manifest.toml");
+        fingerprint += &format!(
+            "{} {} {}\n",
+            toolchain.display(),
+            mtime(&toolchain),
+            mtime(&manifest)
+        );
+    }
+
+    git_hashing::git_blob_sha1_hex(fingerprint.as_bytes())
+}
+
+/// Returns the output of `rustup toolchain list`, if rustup is available.
+fn rustup_toolchain_list(dir: &Path) -> Option<String> {
+    let output = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Finds a nightly toolchain in the output of `rustup toolchain list`: the
+/// undated `nightly` if it's installed, or else the newest dated one.
+pub fn installed_nightly(toolchain_list: &str) -> Option<String> {
+    static DATED: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^nightly(-\d{4}-\d{2}-\d{2})?-").unwrap());
+
+    toolchain_list
+        .lines()
+        .filter_map(|line| DATED.captures(line))
+        .map(|captures| {
+            format!(
+                "nightly{}",
+                captures.get(1).map_or("", |date| date.as_str())
+            )
+        })
+        .max_by_key(|name| (name == "nightly", name.clone()))
+}
+
+/// Finds the newest patch release of Rust `1.{minor}` in the output of `rustup
+/// toolchain list`, returning its version number.
+pub fn installed_release(toolchain_list: &str, minor: u64) -> Option<String> {
+    toolchain_list
+        .lines()
+        .filter_map(|line| line.split(['-', ' ']).next())
+        .filter_map(|version| semver::Version::parse(version).ok())
+        .filter(|version| version.major == 1 && version.minor == minor)
+        .max()
+        .map(|version| version.to_string())
+}
+
+#[test]
+fn test_installed_release() {
+    let toolchain_list = "\
+        stable-x86_64-unknown-linux-gnu (default)\n\
+        nightly-2022-06-01-x86_64-unknown-linux-gnu\n\
+        1.58.0-x86_64-unknown-linux-gnu\n\
+        1.58.1-x86_64-unknown-linux-gnu\n\
+        1.59.0-x86_64-unknown-linux-gnu (override)\n";
+
+    assert_eq!(
+        installed_release(toolchain_list, 58).as_deref(),
+        Some("1.58.1")
+    );
+    assert_eq!(
+        installed_release(toolchain_list, 59).as_deref(),
+        Some("1.59.0")
+    );
+    assert_eq!(installed_release(toolchain_list, 60), None);
+
+    assert_eq!(
+        installed_nightly(toolchain_list).as_deref(),
+        Some("nightly-2022-06-01")
+    );
+    assert_eq!(
+        installed_nightly("nightly-x86_64-apple-darwin\nnightly-2022-06-01-x86_64-apple-darwin\n")
+            .as_deref(),
+        Some("nightly")
+    );
+    assert_eq!(installed_nightly("stable-x86_64-apple-darwin\n"), None);
+}
@@ -9,7 +9,7 @@ static RUST_EPOCH_SECONDS: u64 = 1_431_691_200;
 static RUST_RELEASE_INTERVAL_SECONDS: u64 = 3_628_800;
 
 /// Ordered list of pairs of rust versions that introduced a new edition.
-static RUST_EDITION_RELEASES: &[(u64, u64)] = &[(0, 2015), (31, 2018), (56, 2021)];
+static RUST_EDITION_RELEASES: &[(u64, u64)] = &[(0, 2015), (31, 2018), (56, 2021), (85, 2024)];
 
 /// Returns the minor version number of the most recent Rust release at a given
 /// timestamp. For example, this would return `59` if given a timestamp shortly
@@ -24,6 +24,26 @@ pub fn last_release_at(timestamp: u64) -> u64 {
     }
 }
 
+/// Returns the newest edition supported by the Rust release with the given
+/// minor version number.
+pub fn edition_at_release(minor: u64) -> u64 {
+    RUST_EDITION_RELEASES
+        .iter()
+        .take_while(|(release, _)| *release <= minor)
+        .last()
+        .map(|(_, edition)| *edition)
+        .unwrap_or(2015)
+}
+
+#[test]
+fn test_edition_at_release() {
+    assert_eq!(edition_at_release(0), 2015);
+    assert_eq!(edition_at_release(30), 2015);
+    assert_eq!(edition_at_release(31), 2018);
+    assert_eq!(edition_at_release(59), 2021);
+    assert_eq!(edition_at_release(85), 2024);
+}
+
 #[test]
 fn test_last_release_at() {
     assert_eq!(last_release_at(0), 0);
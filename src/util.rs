@@ -2,14 +2,84 @@ use crate::*;
 
 pub fn help() -> Result<()> {
     println!("#!/usr/bin/env rust");
+    println!();
+    println!(
+        "\
+usage: rust [-v | -q] [run] [--release | --profile <name>] <script.rs> [args...]
+       rust test <script.rs> [test args...]
+       rust check | clippy | fix <script.rs> [cargo args...]
+       rust fmt [--check] <script.rs>
+       rust export <script.rs> <dir>
+       rust install [--name <name>] <script.rs> | --list
+       rust uninstall <name>
+       rust cache list | path <script.rs> | clear [<script.rs>] [--json]
+       rust cache gc
+       rust eval <expression>
+
+If a script fails to compile, rust exits with status {COMPILE_ERROR_EXIT_CODE}.
+Otherwise it exits with the status of the script or the command it ran."
+    );
 
     std::process::exit(0)
 }
 
-pub fn run(path: PathBuf, args: &[OsString]) -> Result<()> {
+pub fn run(path: PathBuf, profile: Option<String>, args: &[OsString]) -> Result<()> {
     let body = std::fs::read_to_string(&path).unwrap();
 
-    compile_and_run(path, body, args)
+    compile_and_run(path, body, profile, args)
+}
+
+pub fn test(path: PathBuf, args: &[OsString]) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    compile_and_test(path, body, args)
+}
+
+pub fn check(path: PathBuf, args: &[OsString]) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    compile_and_check(path, body, "check", args)
+}
+
+pub fn clippy(path: PathBuf, args: &[OsString]) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    compile_and_check(path, body, "clippy", args)
+}
+
+pub fn fmt(path: PathBuf, check: bool) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    format_script(path, body, check)
+}
+
+pub fn fix(path: PathBuf, args: &[OsString]) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    fix_script(path, body, args)
+}
+
+pub fn export(path: PathBuf, dir: PathBuf) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    export_script(path, body, dir)
+}
+
+pub fn install(path: PathBuf, name: Option<String>) -> Result<()> {
+    let body = std::fs::read_to_string(&path)?;
+
+    install_script(path, body, name)
+}
+
+pub fn uninstall(name: &str) -> Result<()> {
+    uninstall_binary(name)
+}
+
+pub fn cache_gc() -> Result<()> {
+    let dirs = DataDirs::new()?;
+    let config = Config::load(&dirs.data_dir)?;
+
+    collect_garbage(&dirs, &config.cache)
 }
 
 pub fn eval(body: String, args: &[OsString]) -> Result<()> {
@@ -19,5 +89,5 @@ pub fn eval(body: String, args: &[OsString]) -> Result<()> {
         .unwrap()
         .join(format!("eval_{}.rs", &hash[..8]));
 
-    compile_and_run(path, body, args)
+    compile_and_run(path, body, None, args)
 }
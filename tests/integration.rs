@@ -15,6 +15,20 @@ fn test_commands() -> Result<()> {
         expect![[r#"
             status: success
             stdout: #!/usr/bin/env rust
+
+                    usage: rust [-v | -q] [run] [--release | --profile <name>] <script.rs> [args...]
+                           rust test <script.rs> [test args...]
+                           rust check | clippy | fix <script.rs> [cargo args...]
+                           rust fmt [--check] <script.rs>
+                           rust export <script.rs> <dir>
+                           rust install [--name <name>] <script.rs> | --list
+                           rust uninstall <name>
+                           rust cache list | path <script.rs> | clear [<script.rs>] [--json]
+                           rust cache gc
+                           rust eval <expression>
+
+                    If a script fails to compile, rust exits with status 65.
+                    Otherwise it exits with the status of the script or the command it ran.
             stderr: none
         "#]],
     )?;
@@ -24,6 +38,20 @@ fn test_commands() -> Result<()> {
         expect![[r#"
             status: success
             stdout: #!/usr/bin/env rust
+
+                    usage: rust [-v | -q] [run] [--release | --profile <name>] <script.rs> [args...]
+                           rust test <script.rs> [test args...]
+                           rust check | clippy | fix <script.rs> [cargo args...]
+                           rust fmt [--check] <script.rs>
+                           rust export <script.rs> <dir>
+                           rust install [--name <name>] <script.rs> | --list
+                           rust uninstall <name>
+                           rust cache list | path <script.rs> | clear [<script.rs>] [--json]
+                           rust cache gc
+                           rust eval <expression>
+
+                    If a script fails to compile, rust exits with status 65.
+                    Otherwise it exits with the status of the script or the command it ran.
             stderr: none
         "#]],
     )?;
@@ -36,6 +64,7 @@ fn test_commands() -> Result<()> {
                         verbosity: Some(
                             8,
                         ),
+                        options: [],
                         subcommand: Help(
                             ArgStream {
                                 args: [
@@ -46,6 +75,20 @@ fn test_commands() -> Result<()> {
                         ),
                     }
                     #!/usr/bin/env rust
+
+                    usage: rust [-v | -q] [run] [--release | --profile <name>] <script.rs> [args...]
+                           rust test <script.rs> [test args...]
+                           rust check | clippy | fix <script.rs> [cargo args...]
+                           rust fmt [--check] <script.rs>
+                           rust export <script.rs> <dir>
+                           rust install [--name <name>] <script.rs> | --list
+                           rust uninstall <name>
+                           rust cache list | path <script.rs> | clear [<script.rs>] [--json]
+                           rust cache gc
+                           rust eval <expression>
+
+                    If a script fails to compile, rust exits with status 65.
+                    Otherwise it exits with the status of the script or the command it ran.
             stderr: none
         "#]],
     )?;
@@ -55,6 +98,20 @@ fn test_commands() -> Result<()> {
         expect![[r#"
             status: success
             stdout: #!/usr/bin/env rust
+
+                    usage: rust [-v | -q] [run] [--release | --profile <name>] <script.rs> [args...]
+                           rust test <script.rs> [test args...]
+                           rust check | clippy | fix <script.rs> [cargo args...]
+                           rust fmt [--check] <script.rs>
+                           rust export <script.rs> <dir>
+                           rust install [--name <name>] <script.rs> | --list
+                           rust uninstall <name>
+                           rust cache list | path <script.rs> | clear [<script.rs>] [--json]
+                           rust cache gc
+                           rust eval <expression>
+
+                    If a script fails to compile, rust exits with status 65.
+                    Otherwise it exits with the status of the script or the command it ran.
             stderr: none
         "#]],
     )?;
@@ -246,6 +303,7 @@ pub fn assert_command(mut command: impl BorrowMut<Command>, expect: Expect) -> R
 
 fn format_output(s: &str) -> String {
     let s = strip_color(s).replace('\n', "\n        ");
+    let s = s.lines().map(str::trim_end).collect::<Vec<_>>().join("\n");
     let s = s.trim_end();
     let s = s.replace(env::current_dir().unwrap().to_str().unwrap(), ".");
     let s = s.replace(::home::home_dir().unwrap().to_str().unwrap(), "~");
@@ -277,7 +335,7 @@ fn ensure_rust_bin_in_path() {
         let env_dir = env::current_dir().unwrap();
         let debug_dir = env_dir.join("target").join("debug");
         let examples_dir = env_dir.join("examples");
-        if !env_path.contains(&debug_dir.to_str().unwrap()) {
+        if !env_path.contains(debug_dir.to_str().unwrap()) {
             env_path = env::join_paths(
                 [debug_dir, examples_dir]
                     .into_iter()